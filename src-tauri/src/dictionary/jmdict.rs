@@ -0,0 +1,354 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use roxmltree::Node;
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use super::{child_text, parse_xml, read_xml};
+use crate::parser::models::{KanjiDatabase, KanjiDatabaseState, KanjiId};
+use crate::text::{normalize_kana, strip_reading_suffix};
+use crate::storage;
+
+// Only entries for words KanjiDamage lists are kept, not the whole dictionary
+pub const JUKUGO_DICTIONARY_PATH: &str = "jukugo_dictionary.json";
+
+// Priority codes JMdict marks a spelling or reading as common with
+const COMMON_PRIORITIES: [&str; 5] = ["news1", "ichi1", "spec1", "spec2", "gai1"];
+
+// Everything the dictionary says about one jukugo, merged across its entries
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct JukugoEntry {
+    // ent_seq of each JMdict entry written this way
+    pub entries: Vec<u64>,
+    // Readings valid for this spelling, in dictionary order
+    pub readings: Vec<String>,
+    pub parts_of_speech: Vec<String>,
+    pub common: bool,
+}
+
+// Keyed by the word as written, without KanjiDamage's trailing reading
+pub type JukugoDictionary = BTreeMap<String, JukugoEntry>;
+
+pub fn load() -> Result<JukugoDictionary, String> {
+    Ok(storage::read_json(JUKUGO_DICTIONARY_PATH)?.unwrap_or_default())
+}
+
+pub struct JmdictState(RwLock<JukugoDictionary>);
+
+impl JmdictState {
+    pub fn new(dictionary: JukugoDictionary) -> Self {
+        Self(RwLock::new(dictionary))
+    }
+
+    pub fn read(&self) -> Result<RwLockReadGuard<'_, JukugoDictionary>, String> {
+        self.0.read().map_err(|_| "Failed to lock jukugo dictionary".to_string())
+    }
+
+    pub fn write(&self) -> Result<RwLockWriteGuard<'_, JukugoDictionary>, String> {
+        self.0.write().map_err(|_| "Failed to lock jukugo dictionary".to_string())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReadingMismatch {
+    pub kanji_id: KanjiId,
+    pub japanese: String,
+    pub scraped: String,
+    pub dictionary: Vec<String>,
+}
+
+fn is_common(element: Node, priority: &str) -> bool {
+    element
+        .children()
+        .filter(|c| c.has_tag_name(priority))
+        .filter_map(|c| c.text())
+        .any(|p| COMMON_PRIORITIES.contains(&p.trim()))
+}
+
+fn push_unique(values: &mut Vec<String>, value: &str) {
+    if !values.iter().any(|v| v == value) {
+        values.push(value.to_string());
+    }
+}
+
+// Merge one <entry> into the words it's written as, skipping words nobody asked for
+fn add_entry(dictionary: &mut JukugoDictionary, entry: Node, words: &HashSet<String>) {
+    let seq = child_text(entry, "ent_seq").and_then(|s| s.parse().ok()).unwrap_or(0);
+    let spellings: Vec<(&str, bool)> = entry
+        .children()
+        .filter(|c| c.has_tag_name("k_ele"))
+        .filter_map(|k| child_text(k, "keb").map(|keb| (keb, is_common(k, "ke_pri"))))
+        .collect();
+    let readings: Vec<Node> = entry.children().filter(|c| c.has_tag_name("r_ele")).collect();
+
+    // Kana-only words are looked up by their reading
+    let written: Vec<(&str, bool)> = if spellings.is_empty() {
+        readings
+            .iter()
+            .filter_map(|r| child_text(*r, "reb").map(|reb| (reb, is_common(*r, "re_pri"))))
+            .collect()
+    } else {
+        spellings
+    };
+
+    let parts_of_speech: Vec<&str> = entry
+        .children()
+        .filter(|c| c.has_tag_name("sense"))
+        .flat_map(|sense| sense.children().filter(|c| c.has_tag_name("pos")))
+        .filter_map(|pos| pos.text())
+        .map(str::trim)
+        .collect();
+
+    for (word, spelling_common) in written {
+        if !words.contains(word) {
+            continue;
+        }
+
+        let linked = dictionary.entry(word.to_string()).or_default();
+        if !linked.entries.contains(&seq) {
+            linked.entries.push(seq);
+        }
+        linked.common |= spelling_common;
+
+        for reading in &readings {
+            let Some(reb) = child_text(*reading, "reb") else {
+                continue;
+            };
+            // re_restr limits a reading to some spellings, re_nokanji means it isn't a reading of the kanji
+            let restricted: Vec<&str> = reading
+                .children()
+                .filter(|c| c.has_tag_name("re_restr"))
+                .filter_map(|c| c.text())
+                .collect();
+            let nokanji = reading.children().any(|c| c.has_tag_name("re_nokanji"));
+            if (!restricted.is_empty() && !restricted.contains(&word)) || (nokanji && reb != word) {
+                continue;
+            }
+
+            push_unique(&mut linked.readings, reb);
+        }
+        for pos in &parts_of_speech {
+            push_unique(&mut linked.parts_of_speech, pos);
+        }
+    }
+}
+
+pub fn parse_jmdict(text: &str, path: &str, words: &HashSet<String>) -> Result<(usize, JukugoDictionary), String> {
+    let document = parse_xml(text, path)?;
+
+    let mut dictionary = JukugoDictionary::new();
+    let mut entries = 0;
+    for entry in document.root_element().children().filter(|n| n.has_tag_name("entry")) {
+        add_entry(&mut dictionary, entry, words);
+        entries += 1;
+    }
+
+    if entries == 0 {
+        return Err(format!("No entries found in {}, is it a JMdict file?", path));
+    }
+    Ok((entries, dictionary))
+}
+
+fn jukugo_words(db: &KanjiDatabase) -> HashSet<String> {
+    db.details
+        .values()
+        .flat_map(|d| &d.jukugo)
+        .map(|j| strip_reading_suffix(&j.japanese).to_string())
+        .collect()
+}
+
+// Jukugo whose scraped reading isn't one the dictionary gives for that spelling. Words
+// the dictionary doesn't know are left out, there's nothing to compare them to.
+pub fn reading_mismatches(db: &KanjiDatabase, dictionary: &JukugoDictionary) -> Vec<ReadingMismatch> {
+    let mut seen_words = HashSet::new();
+    let mut mismatches = Vec::new();
+
+    for detail in db.details.values() {
+        for jukugo in &detail.jukugo {
+            let word = strip_reading_suffix(&jukugo.japanese);
+            let Some(entry) = dictionary.get(word) else {
+                continue;
+            };
+            if !seen_words.insert(word) {
+                continue;
+            }
+
+            let scraped = normalize_kana(&jukugo.reading);
+            if !entry.readings.iter().any(|r| normalize_kana(r) == scraped) {
+                mismatches.push(ReadingMismatch {
+                    kanji_id: detail.link.clone(),
+                    japanese: word.to_string(),
+                    scraped: jukugo.reading.clone(),
+                    dictionary: entry.readings.clone(),
+                });
+            }
+        }
+    }
+
+    mismatches
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JmdictImport {
+    pub entries: usize,
+    // Distinct cached jukugo, and how many of them the dictionary has
+    pub words: usize,
+    pub linked: usize,
+    pub mismatches: Vec<ReadingMismatch>,
+}
+
+// Link every cached jukugo to its JMdict entries, replacing anything imported before.
+// Jukugo on pages cached later are picked up by importing again.
+#[tauri::command]
+pub async fn import_jmdict(
+    state: State<'_, KanjiDatabaseState>,
+    jmdict: State<'_, JmdictState>,
+    path: String
+) -> Result<JmdictImport, String> {
+    let words = {
+        let db = state.read()?;
+        jukugo_words(&db)
+    };
+    let word_count = words.len();
+
+    let (entries, dictionary) = tauri::async_runtime::spawn_blocking(move || {
+        parse_jmdict(&read_xml(&path)?, &path, &words)
+    })
+    .await
+    .map_err(|e| format!("Failed to import JMdict: {}", e))??;
+
+    storage::write_json(JUKUGO_DICTIONARY_PATH, &dictionary)?;
+    let mismatches = {
+        let db = state.read()?;
+        reading_mismatches(&db, &dictionary)
+    };
+    let linked = dictionary.len();
+    *jmdict.write()? = dictionary;
+
+    Ok(JmdictImport {
+        entries,
+        words: word_count,
+        linked,
+        mismatches,
+    })
+}
+
+// Dictionary data for each of `words` the dictionary has, written with or without the
+// trailing reading
+#[tauri::command]
+pub fn get_jukugo_dictionary(
+    jmdict: State<'_, JmdictState>,
+    words: Vec<String>
+) -> Result<BTreeMap<String, JukugoEntry>, String> {
+    let dictionary = jmdict.read()?;
+    Ok(words
+        .iter()
+        .filter_map(|word| {
+            let word = strip_reading_suffix(word);
+            dictionary.get(word).map(|entry| (word.to_string(), entry.clone()))
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub fn get_reading_mismatches(
+    state: State<'_, KanjiDatabaseState>,
+    jmdict: State<'_, JmdictState>
+) -> Result<Vec<ReadingMismatch>, String> {
+    let db = state.read()?;
+    let dictionary = jmdict.read()?;
+    Ok(reading_mismatches(&db, &dictionary))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(entries: &str, words: &[&str]) -> JukugoDictionary {
+        let words = words.iter().map(|w| w.to_string()).collect();
+        parse_jmdict(&format!("<JMdict>{}</JMdict>", entries), "test.xml", &words).unwrap().1
+    }
+
+    #[test]
+    fn restricted_readings_only_apply_to_their_spellings() {
+        let dictionary = parse(
+            "<entry><ent_seq>1</ent_seq>
+                <k_ele><keb>明日</keb></k_ele><k_ele><keb>明後日</keb></k_ele>
+                <r_ele><reb>あした</reb><re_restr>明日</re_restr></r_ele>
+                <r_ele><reb>あさって</reb><re_restr>明後日</re_restr></r_ele>
+                <r_ele><reb>みょうにち</reb></r_ele>
+            </entry>",
+            &["明日", "明後日"],
+        );
+
+        assert_eq!(dictionary["明日"].readings, ["あした", "みょうにち"]);
+        assert_eq!(dictionary["明後日"].readings, ["あさって", "みょうにち"]);
+    }
+
+    #[test]
+    fn nokanji_readings_are_not_readings_of_the_spelling() {
+        let dictionary = parse(
+            "<entry><ent_seq>2</ent_seq>
+                <k_ele><keb>合羽</keb></k_ele>
+                <r_ele><reb>かっぱ</reb></r_ele>
+                <r_ele><reb>カッパ</reb><re_nokanji/></r_ele>
+            </entry>",
+            &["合羽"],
+        );
+
+        assert_eq!(dictionary["合羽"].readings, ["かっぱ"]);
+    }
+
+    #[test]
+    fn kana_only_entries_are_keyed_by_reading() {
+        let dictionary = parse(
+            "<entry><ent_seq>3</ent_seq>
+                <r_ele><reb>ありがとう</reb><re_pri>spec1</re_pri></r_ele>
+            </entry>",
+            &["ありがとう"],
+        );
+
+        let entry = &dictionary["ありがとう"];
+        assert_eq!(entry.readings, ["ありがとう"]);
+        assert!(entry.common);
+    }
+
+    #[test]
+    fn entries_merge_and_unlisted_words_are_skipped() {
+        let dictionary = parse(
+            "<entry><ent_seq>4</ent_seq>
+                <k_ele><keb>生物</keb><ke_pri>news1</ke_pri></k_ele><k_ele><keb>生き物</keb></k_ele>
+                <r_ele><reb>せいぶつ</reb></r_ele>
+                <sense><pos>noun</pos></sense>
+            </entry>
+            <entry><ent_seq>5</ent_seq>
+                <k_ele><keb>生物</keb><ke_pri>news2</ke_pri></k_ele>
+                <r_ele><reb>なまもの</reb></r_ele>
+                <sense><pos>noun</pos><pos>adjective</pos></sense>
+            </entry>",
+            &["生物"],
+        );
+
+        assert_eq!(dictionary.len(), 1);
+        let entry = &dictionary["生物"];
+        assert_eq!(entry.entries, [4, 5]);
+        assert_eq!(entry.readings, ["せいぶつ", "なまもの"]);
+        assert_eq!(entry.parts_of_speech, ["noun", "adjective"]);
+        assert!(entry.common);
+    }
+
+    #[test]
+    fn uncommon_priorities_are_not_common() {
+        let dictionary = parse(
+            "<entry><ent_seq>6</ent_seq>
+                <k_ele><keb>稀覯</keb><ke_pri>news2</ke_pri></k_ele>
+                <r_ele><reb>きこう</reb></r_ele>
+            </entry>",
+            &["稀覯"],
+        );
+
+        assert!(!dictionary["稀覯"].common);
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use roxmltree::Node;
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use super::{child, child_text, parse_xml, read_xml};
+use crate::parser::models::{KanjiDatabaseState, KanjiId};
+use crate::storage;
+
+// Only the entries joined to the kanji list are kept, the full file is ~13,000 kanji
+pub const KANJI_METADATA_PATH: &str = "kanji_metadata.json";
+
+// What KANJIDIC2 knows about a kanji that KanjiDamage doesn't
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct KanjiMetadata {
+    pub stroke_count: Option<u8>,
+    // The old four-level JLPT, 4 being the easiest. KANJIDIC2 predates N1-N5.
+    pub jlpt: Option<u8>,
+    // 1-6 for the grade taught in school, 8 for the rest of the jouyou, 9-10 for jinmeiyou
+    pub grade: Option<u8>,
+    // Rank among the 2,500 most used kanji in newspapers
+    pub frequency: Option<u32>,
+}
+
+pub type KanjiMetadataMap = BTreeMap<KanjiId, KanjiMetadata>;
+
+pub fn load() -> Result<KanjiMetadataMap, String> {
+    Ok(storage::read_json(KANJI_METADATA_PATH)?.unwrap_or_default())
+}
+
+pub struct KanjidicState(RwLock<KanjiMetadataMap>);
+
+impl KanjidicState {
+    pub fn new(metadata: KanjiMetadataMap) -> Self {
+        Self(RwLock::new(metadata))
+    }
+
+    pub fn read(&self) -> Result<RwLockReadGuard<'_, KanjiMetadataMap>, String> {
+        self.0.read().map_err(|_| "Failed to lock kanji metadata".to_string())
+    }
+
+    pub fn write(&self) -> Result<RwLockWriteGuard<'_, KanjiMetadataMap>, String> {
+        self.0.write().map_err(|_| "Failed to lock kanji metadata".to_string())
+    }
+}
+
+// Narrows a kanji selection down by its KANJIDIC2 data. Kanji without an entry only
+// pass when nothing is asked of it.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct MetadataFilter {
+    pub min_strokes: Option<u8>,
+    pub max_strokes: Option<u8>,
+    pub jlpt: Option<u8>,
+    pub grade: Option<u8>,
+    // Only kanji ranked this frequent or better
+    pub max_frequency: Option<u32>,
+}
+
+impl MetadataFilter {
+    pub fn is_empty(&self) -> bool {
+        self.min_strokes.is_none()
+            && self.max_strokes.is_none()
+            && self.jlpt.is_none()
+            && self.grade.is_none()
+            && self.max_frequency.is_none()
+    }
+
+    pub fn matches(&self, metadata: Option<&KanjiMetadata>) -> bool {
+        let Some(metadata) = metadata else {
+            return self.is_empty();
+        };
+        let strokes = metadata.stroke_count;
+
+        !(self.min_strokes.is_some_and(|min| strokes.is_none_or(|s| s < min))
+            || self.max_strokes.is_some_and(|max| strokes.is_none_or(|s| s > max))
+            || self.jlpt.is_some_and(|jlpt| metadata.jlpt != Some(jlpt))
+            || self.grade.is_some_and(|grade| metadata.grade != Some(grade))
+            || self.max_frequency.is_some_and(|max| metadata.frequency.is_none_or(|f| f > max)))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KanjiSort {
+    #[default]
+    Index,
+    Strokes,
+    Jlpt,
+    Grade,
+    Frequency,
+}
+
+impl KanjiSort {
+    // Easiest first, kanji missing the value last, ties in KanjiDamage order
+    pub fn key(self, index: i32, metadata: Option<&KanjiMetadata>) -> (bool, i64, i32) {
+        let value = match self {
+            KanjiSort::Index => return (false, 0, index),
+            KanjiSort::Strokes => metadata.and_then(|m| m.stroke_count).map(i64::from),
+            KanjiSort::Jlpt => metadata.and_then(|m| m.jlpt).map(|level| -i64::from(level)),
+            KanjiSort::Grade => metadata.and_then(|m| m.grade).map(i64::from),
+            KanjiSort::Frequency => metadata.and_then(|m| m.frequency).map(i64::from),
+        };
+        (value.is_none(), value.unwrap_or(0), index)
+    }
+}
+
+fn misc_value<T: FromStr>(misc: Option<Node>, name: &str) -> Option<T> {
+    misc.and_then(|misc| child_text(misc, name)).and_then(|text| text.parse().ok())
+}
+
+// Every <character> in the file, keyed by its literal
+pub fn parse_kanjidic(text: &str, path: &str) -> Result<HashMap<String, KanjiMetadata>, String> {
+    let document = parse_xml(text, path)?;
+
+    let mut entries = HashMap::new();
+    for character in document.root_element().children().filter(|n| n.has_tag_name("character")) {
+        let Some(literal) = child_text(character, "literal") else {
+            continue;
+        };
+        let misc = child(character, "misc");
+
+        // The first stroke count is the right one, the rest are common miscounts
+        entries.insert(literal.to_string(), KanjiMetadata {
+            stroke_count: misc_value(misc, "stroke_count"),
+            jlpt: misc_value(misc, "jlpt"),
+            grade: misc_value(misc, "grade"),
+            frequency: misc_value(misc, "freq"),
+        });
+    }
+
+    if entries.is_empty() {
+        return Err(format!("No kanji found in {}, is it a KANJIDIC2 file?", path));
+    }
+    Ok(entries)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct KanjidicImport {
+    pub entries: usize,
+    pub matched: usize,
+    // Kanji list entries with no KANJIDIC2 entry, mostly radicals only available as images
+    pub unmatched: usize,
+}
+
+// Read a KANJIDIC2 file and attach its data to every kanji in the list with the same
+// character, replacing anything imported before
+#[tauri::command]
+pub async fn import_kanjidic(
+    state: State<'_, KanjiDatabaseState>,
+    kanjidic: State<'_, KanjidicState>,
+    path: String
+) -> Result<KanjidicImport, String> {
+    let entries = tauri::async_runtime::spawn_blocking(move || parse_kanjidic(&read_xml(&path)?, &path))
+        .await
+        .map_err(|e| format!("Failed to import KANJIDIC2: {}", e))??;
+
+    let (metadata, listed) = {
+        let db = state.read()?;
+        let metadata: KanjiMetadataMap = db.kanjis
+            .iter()
+            .filter_map(|k| entries.get(&k.kanji).map(|m| (k.link.clone(), m.clone())))
+            .collect();
+        (metadata, db.kanjis.len())
+    };
+
+    storage::write_json(KANJI_METADATA_PATH, &metadata)?;
+    let summary = KanjidicImport {
+        entries: entries.len(),
+        matched: metadata.len(),
+        unmatched: listed - metadata.len(),
+    };
+    *kanjidic.write()? = metadata;
+
+    Ok(summary)
+}
+
+#[tauri::command]
+pub fn get_kanji_metadata(kanjidic: State<'_, KanjidicState>, url: String) -> Result<Option<KanjiMetadata>, String> {
+    let kanji_id = KanjiId::parse(&url).ok_or_else(|| format!("Invalid kanji link: {}", url))?;
+    Ok(kanjidic.read()?.get(&kanji_id).cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(characters: &str) -> Result<HashMap<String, KanjiMetadata>, String> {
+        parse_kanjidic(&format!("<kanjidic2>{}</kanjidic2>", characters), "test.xml")
+    }
+
+    #[test]
+    fn reads_misc_values() {
+        let entries = parse(
+            "<character><literal>日</literal><misc>
+                <grade>1</grade><stroke_count>4</stroke_count><freq>1</freq><jlpt>4</jlpt>
+            </misc></character>",
+        )
+        .unwrap();
+
+        let metadata = &entries["日"];
+        assert_eq!(metadata.stroke_count, Some(4));
+        assert_eq!(metadata.grade, Some(1));
+        assert_eq!(metadata.frequency, Some(1));
+        assert_eq!(metadata.jlpt, Some(4));
+    }
+
+    #[test]
+    fn first_stroke_count_wins() {
+        let entries = parse(
+            "<character><literal>必</literal><misc>
+                <stroke_count>5</stroke_count><stroke_count>4</stroke_count><stroke_count>6</stroke_count>
+            </misc></character>",
+        )
+        .unwrap();
+
+        assert_eq!(entries["必"].stroke_count, Some(5));
+    }
+
+    #[test]
+    fn missing_values_are_none() {
+        let entries = parse(
+            "<character><literal>丶</literal></character>
+            <character><literal>乂</literal><misc><stroke_count>2</stroke_count></misc></character>",
+        )
+        .unwrap();
+
+        assert!(entries["丶"].stroke_count.is_none());
+        assert_eq!(entries["乂"].stroke_count, Some(2));
+        assert!(entries["乂"].jlpt.is_none() && entries["乂"].grade.is_none() && entries["乂"].frequency.is_none());
+    }
+
+    #[test]
+    fn characters_without_a_literal_are_skipped() {
+        let entries = parse(
+            "<character><misc><stroke_count>3</stroke_count></misc></character>
+            <character><literal>口</literal></character>",
+        )
+        .unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert!(parse("<header/>").is_err());
+    }
+}
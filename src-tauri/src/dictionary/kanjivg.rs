@@ -0,0 +1,282 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use roxmltree::Node;
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use super::{parse_xml, read_xml};
+use crate::parser::models::{KanjiDatabaseState, KanjiId, KanjiListing};
+use crate::storage;
+
+pub const KANJI_STROKES_PATH: &str = "kanji_strokes.json";
+
+const KVG_NAMESPACE: &str = "http://kanjivg.tagaini.net";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Stroke {
+    // SVG path data in KanjiVG's 109x109 box
+    pub path: String,
+    // The CJK stroke character, e.g. "㇐"
+    pub stroke_type: Option<String>,
+}
+
+pub type KanjiStrokesMap = BTreeMap<KanjiId, Vec<Stroke>>;
+
+pub fn load() -> Result<KanjiStrokesMap, String> {
+    Ok(storage::read_json(KANJI_STROKES_PATH)?.unwrap_or_default())
+}
+
+pub struct KanjivgState(RwLock<KanjiStrokesMap>);
+
+impl KanjivgState {
+    pub fn new(strokes: KanjiStrokesMap) -> Self {
+        Self(RwLock::new(strokes))
+    }
+
+    pub fn read(&self) -> Result<RwLockReadGuard<'_, KanjiStrokesMap>, String> {
+        self.0.read().map_err(|_| "Failed to lock stroke data".to_string())
+    }
+
+    pub fn write(&self) -> Result<RwLockWriteGuard<'_, KanjiStrokesMap>, String> {
+        self.0.write().map_err(|_| "Failed to lock stroke data".to_string())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct KanjiStrokes {
+    pub kanji_id: KanjiId,
+    pub kanji: String,
+    pub stroke_count: usize,
+    // In stroke order
+    pub strokes: Vec<Stroke>,
+}
+
+// KanjiVG names kanji by code point, e.g. "04e00". Variants like "04e00-Kaisho" are skipped.
+fn parse_code_point(hex: &str) -> Option<char> {
+    if hex.contains('-') {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+}
+
+// The SVGs only declare the kvg namespace in their DTD, which isn't applied, so declare it
+// on the root element too
+fn declare_kvg_namespace(text: String, root: &str) -> String {
+    if text.contains("xmlns:kvg=") {
+        return text;
+    }
+    text.replacen(&format!("<{} ", root), &format!("<{} xmlns:kvg=\"{}\" ", root, KVG_NAMESPACE), 1)
+}
+
+// Stroke paths have ids ending in "-s1", "-s2"... which give the order
+fn strokes(kanji: Node) -> Vec<Stroke> {
+    let mut numbered: Vec<(u32, Stroke)> = kanji
+        .descendants()
+        .filter(|n| n.has_tag_name("path"))
+        .filter_map(|path| {
+            let number = path.attribute("id")?.rsplit_once("-s")?.1.parse().ok()?;
+            Some((number, Stroke {
+                path: path.attribute("d")?.to_string(),
+                stroke_type: path.attribute((KVG_NAMESPACE, "type")).map(str::to_string),
+            }))
+        })
+        .collect();
+
+    numbered.sort_by_key(|(number, _)| *number);
+    numbered.into_iter().map(|(_, stroke)| stroke).collect()
+}
+
+// The combined kanjivg.xml, with one <kanji id="kvg:kanji_04e00"> per character
+fn parse_combined(path: &str) -> Result<HashMap<char, Vec<Stroke>>, String> {
+    parse_combined_text(read_xml(path)?, path)
+}
+
+fn parse_combined_text(text: String, path: &str) -> Result<HashMap<char, Vec<Stroke>>, String> {
+    let text = declare_kvg_namespace(text, "kanjivg");
+    let document = parse_xml(&text, path)?;
+
+    Ok(document
+        .root_element()
+        .children()
+        .filter(|n| n.has_tag_name("kanji"))
+        .filter_map(|kanji| {
+            let character = parse_code_point(kanji.attribute("id")?.strip_prefix("kvg:kanji_")?)?;
+            Some((character, strokes(kanji)))
+        })
+        .collect())
+}
+
+// The per-character SVGs, named after the code point
+fn parse_svg_directory(path: &str) -> Result<HashMap<char, Vec<Stroke>>, String> {
+    let entries = fs::read_dir(path).map_err(|e| format!("Failed to read directory {}: {}", path, e))?;
+
+    let mut kanji = HashMap::new();
+    for entry in entries {
+        let file = entry.map_err(|e| format!("Failed to read directory {}: {}", path, e))?.path();
+        if file.extension().is_none_or(|extension| extension != "svg") {
+            continue;
+        }
+        let Some(character) = file.file_stem().and_then(|stem| stem.to_str()).and_then(parse_code_point) else {
+            continue;
+        };
+
+        let file = file.to_string_lossy();
+        let text = declare_kvg_namespace(read_xml(&file)?, "svg");
+        let document = parse_xml(&text, &file)?;
+        kanji.insert(character, strokes(document.root_element()));
+    }
+
+    Ok(kanji)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct KanjivgImport {
+    pub entries: usize,
+    pub matched: usize,
+    // Kanji list entries KanjiVG has no strokes for, mostly radicals only available as images
+    pub unmatched: usize,
+}
+
+// Load stroke data from either the combined kanjivg.xml or a directory of KanjiVG SVGs,
+// replacing anything imported before
+#[tauri::command]
+pub async fn import_kanjivg(
+    state: State<'_, KanjiDatabaseState>,
+    kanjivg: State<'_, KanjivgState>,
+    path: String
+) -> Result<KanjivgImport, String> {
+    let entries = tauri::async_runtime::spawn_blocking(move || {
+        if Path::new(&path).is_dir() {
+            parse_svg_directory(&path)
+        } else {
+            parse_combined(&path)
+        }
+    })
+    .await
+    .map_err(|e| format!("Failed to import KanjiVG: {}", e))??;
+
+    if entries.is_empty() {
+        return Err("No KanjiVG stroke data found".to_string());
+    }
+
+    let (strokes, listed) = {
+        let db = state.read()?;
+        let strokes: KanjiStrokesMap = db.kanjis
+            .iter()
+            .filter_map(|k| {
+                let mut chars = k.kanji.chars();
+                let character = chars.next().filter(|_| chars.next().is_none())?;
+                let strokes = entries.get(&character).filter(|s| !s.is_empty())?;
+                Some((k.link.clone(), strokes.clone()))
+            })
+            .collect();
+        (strokes, db.kanjis.len())
+    };
+
+    storage::write_json(KANJI_STROKES_PATH, &strokes)?;
+    let summary = KanjivgImport {
+        entries: entries.len(),
+        matched: strokes.len(),
+        unmatched: listed - strokes.len(),
+    };
+    *kanjivg.write()? = strokes;
+
+    Ok(summary)
+}
+
+#[tauri::command]
+pub fn get_kanji_strokes(
+    state: State<'_, KanjiDatabaseState>,
+    kanjivg: State<'_, KanjivgState>,
+    url: String
+) -> Result<Option<KanjiStrokes>, String> {
+    let kanji_id = KanjiId::parse(&url).ok_or_else(|| format!("Invalid kanji link: {}", url))?;
+    let Some(strokes) = kanjivg.read()?.get(&kanji_id).cloned() else {
+        return Ok(None);
+    };
+
+    // Only kanji written as a single character have strokes, so there's no image link to resolve
+    let db = state.read()?;
+    let kanji = KanjiListing::search_by_index(&db.kanjis, kanji_id.index as i32)
+        .map(|k| k.kanji.clone())
+        .unwrap_or_default();
+
+    Ok(Some(KanjiStrokes {
+        kanji,
+        stroke_count: strokes.len(),
+        kanji_id,
+        strokes,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SVG: &str = r#"<svg xmlns="http://www.w3.org/2000/svg" width="109" height="109">
+<g id="kvg:StrokePaths_04e8c">
+<g id="kvg:04e8c" kvg:element="二">
+    <path id="kvg:04e8c-s2" kvg:type="㇐" d="M14.5,81.5c4,1,8,1,12,0"/>
+    <path id="kvg:04e8c-s1" kvg:type="㇐" d="M27.5,29.5c3,1,6,1,9,0"/>
+</g>
+</g>
+</svg>"#;
+
+    fn parse_strokes(text: &str, root: &str) -> Vec<Stroke> {
+        let text = declare_kvg_namespace(text.to_string(), root);
+        let document = parse_xml(&text, "test.svg").unwrap();
+        strokes(document.root_element())
+    }
+
+    #[test]
+    fn code_points_skip_variants() {
+        assert_eq!(parse_code_point("04e00"), Some('一'));
+        assert_eq!(parse_code_point("04e00-Kaisho"), None);
+        assert_eq!(parse_code_point("nope"), None);
+    }
+
+    #[test]
+    fn namespace_is_declared_once() {
+        let declared = declare_kvg_namespace(SVG.to_string(), "svg");
+        assert!(declared.starts_with(&format!("<svg xmlns:kvg=\"{}\" ", KVG_NAMESPACE)));
+        assert_eq!(declare_kvg_namespace(declared.clone(), "svg"), declared);
+    }
+
+    #[test]
+    fn strokes_are_ordered_by_number_with_their_type() {
+        let strokes = parse_strokes(SVG, "svg");
+
+        let paths: Vec<&str> = strokes.iter().map(|s| s.path.as_str()).collect();
+        assert_eq!(paths, ["M27.5,29.5c3,1,6,1,9,0", "M14.5,81.5c4,1,8,1,12,0"]);
+        assert!(strokes.iter().all(|s| s.stroke_type.as_deref() == Some("㇐")));
+    }
+
+    #[test]
+    fn combined_file_skips_variants() {
+        let combined = r#"<kanjivg xmlns="http://kanjivg.tagaini.net">
+<kanji id="kvg:kanji_04e00"><g><path id="kvg:04e00-s1" kvg:type="㇐" d="M11,54"/></g></kanji>
+<kanji id="kvg:kanji_04e00-Kaisho"><g><path id="kvg:04e00-Kaisho-s1" d="M12,55"/></g></kanji>
+</kanjivg>"#;
+
+        let kanji = parse_combined_text(combined.to_string(), "kanjivg.xml").unwrap();
+        assert_eq!(kanji.len(), 1);
+        assert_eq!(kanji[&'一'][0].path, "M11,54");
+        assert_eq!(kanji[&'一'][0].stroke_type.as_deref(), Some("㇐"));
+    }
+
+    #[test]
+    fn paths_without_a_stroke_number_are_skipped() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg">
+<path id="kvg:04e00-s1" d="M11,54c25,-2,60,-3,87,-1"/>
+<path id="kvg:StrokeNumbers_04e00" d="M0,0"/>
+<path d="M1,1"/>
+</svg>"#;
+
+        let strokes = parse_strokes(svg, "svg");
+        assert_eq!(strokes.len(), 1);
+        assert!(strokes[0].stroke_type.is_none());
+    }
+}
@@ -0,0 +1,28 @@
+pub mod jmdict;
+pub mod kanjidic;
+pub mod kanjivg;
+
+use std::fs;
+
+use roxmltree::{Document, Node, ParsingOptions};
+
+pub fn read_xml(path: &str) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("Failed to read file {}: {}", path, e))
+}
+
+// The EDRDG dictionaries are distributed with their DTD inline, so it has to be allowed
+pub fn parse_xml<'a>(text: &'a str, path: &str) -> Result<Document<'a>, String> {
+    let options = ParsingOptions {
+        allow_dtd: true,
+        ..ParsingOptions::default()
+    };
+    Document::parse_with_options(text, options).map_err(|e| format!("Failed to parse XML in {}: {}", path, e))
+}
+
+pub fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|c| c.has_tag_name(name))
+}
+
+pub fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name).and_then(|c| c.text()).map(str::trim)
+}
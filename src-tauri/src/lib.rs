@@ -1,26 +1,80 @@
 use tauri::{generate_context, Manager};
 
+mod dictionary;
 mod parser;
+mod practice;
+mod storage;
+mod text;
+mod user_data;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .setup(|app| {
+            // Which site to scrape and how to reach it
+            let scraper_config = parser::config::ScraperConfig::load()
+                .expect("Failed to load scraper config");
+
+            // One HTTP client shared by every scraper
+            let http_config = parser::http::HttpConfig::load()
+                .expect("Failed to load HTTP config");
+            let http_client = parser::http::HttpClient::new(http_config, scraper_config.proxy.as_deref())
+                .expect("Failed to build HTTP client");
+
             // Initialize database with fetched data
             tauri::async_runtime::block_on(async {
-                // First fetch/load the kanji list
-                let kanji_list = parser::fetch_and_save_kanji_list()
+                // Load the kanji list and cached details, fetching the list if needed
+                let db = parser::load_kanji_database(&http_client, &scraper_config)
                     .await
-                    .expect("Failed to get kanji list");
-                
-                // Create database with the fetched data
-                let db = parser::models::KanjiDatabase {
-                    kanjis: kanji_list
-                };
+                    .expect("Failed to load kanji database");
                 
                 let db_state = parser::models::KanjiDatabaseState::new(db);
                 app.manage(db_state);
             });
+
+            app.manage(parser::http::HttpClientState::new(http_client));
+            app.manage(parser::config::ScraperConfigState::new(scraper_config));
+
+            let review_log = practice::review_log::ReviewLog::load()
+                .expect("Failed to load review log");
+            app.manage(practice::review_log::ReviewLogState::new(review_log));
+
+            let user_answers = user_data::answers::load()
+                .expect("Failed to load user answers");
+            app.manage(user_data::answers::UserAnswersState::new(user_answers));
+
+            let notes = user_data::notes::load()
+                .expect("Failed to load kanji notes");
+            app.manage(user_data::notes::NotesState::new(notes));
+
+            let study_lists = user_data::lists::StudyLists::load()
+                .expect("Failed to load study lists");
+            app.manage(user_data::lists::StudyListsState::new(study_lists));
+            app.manage(user_data::history::HistoryState::new());
+
+            let kanji_metadata = dictionary::kanjidic::load()
+                .expect("Failed to load kanji metadata");
+            app.manage(dictionary::kanjidic::KanjidicState::new(kanji_metadata));
+
+            let jukugo_dictionary = dictionary::jmdict::load()
+                .expect("Failed to load jukugo dictionary");
+            app.manage(dictionary::jmdict::JmdictState::new(jukugo_dictionary));
+
+            let kanji_strokes = dictionary::kanjivg::load()
+                .expect("Failed to load stroke data");
+            app.manage(dictionary::kanjivg::KanjivgState::new(kanji_strokes));
+
+            let scheduler = practice::scheduler::Scheduler::load()
+                .expect("Failed to load review schedule");
+            app.manage(practice::scheduler::SchedulerState::new(scheduler));
+
+            let session = practice::session::SessionState::load()
+                .expect("Failed to load practice session");
+            app.manage(session);
+
+            let lessons = practice::lessons::Lessons::load()
+                .expect("Failed to load lessons");
+            app.manage(practice::lessons::LessonState::new(lessons));
             
             Ok(())
         })
@@ -33,9 +87,80 @@ pub fn run() {
             parser::update_kanji_practice,
             parser::initialize_practice_pool,
             parser::refresh_kanji_data,
+            parser::get_kanji_changelog,
+            parser::get_http_config,
+            parser::update_http_config,
+            parser::get_scraper_config,
+            parser::update_scraper_config,
+            parser::tags::get_tag_index,
+            parser::tags::kanji_by_tag,
+            parser::tags::jukugo_by_tag,
+            parser::filter::filter_kanji,
+            dictionary::kanjidic::import_kanjidic,
+            dictionary::kanjidic::get_kanji_metadata,
+            dictionary::jmdict::import_jmdict,
+            dictionary::jmdict::get_jukugo_dictionary,
+            dictionary::jmdict::get_reading_mismatches,
+            dictionary::kanjivg::import_kanjivg,
+            dictionary::kanjivg::get_kanji_strokes,
+            practice::bulk::bulk_update_practice,
+            practice::build_practice_cards,
+            practice::build_choice_cards,
+            practice::grade_practice_answer,
+            practice::submit_practice_answer,
+            practice::submit_practice_rating,
+            practice::session::start_session,
+            practice::session::get_session,
+            practice::session::next_card,
+            practice::session::submit_answer,
+            practice::session::rate_answer,
+            practice::session::end_session,
+            practice::scheduler::get_scheduler_config,
+            practice::scheduler::update_scheduler_config,
+            practice::scheduler::get_scheduler_profiles,
+            practice::scheduler::select_scheduler_profile,
+            practice::scheduler::delete_scheduler_profile,
+            practice::scheduler::get_leeches,
+            practice::scheduler::get_remediation_queue,
+            practice::scheduler::complete_remediation,
+            practice::scheduler::set_suspended,
+            practice::scheduler::reset_schedule,
+            practice::scheduler::optimize_fsrs,
+            user_data::answers::get_user_answers,
+            user_data::answers::set_user_answers,
+            user_data::notes::get_kanji_notes,
+            user_data::notes::set_kanji_notes,
+            user_data::lists::get_study_lists,
+            user_data::lists::create_study_list,
+            user_data::lists::rename_study_list,
+            user_data::lists::delete_study_list,
+            user_data::lists::add_to_study_list,
+            user_data::lists::remove_from_study_list,
+            user_data::lists::get_study_list_kanji,
+            user_data::lists::export_study_lists,
+            user_data::export_user_data,
+            user_data::import_user_data,
+            user_data::history::get_history,
+            user_data::history::undo,
+            user_data::history::redo,
+            practice::lessons::get_lesson_config,
+            practice::lessons::update_lesson_config,
+            practice::lessons::get_next_lesson,
+            practice::lessons::get_lesson_prerequisites,
+            practice::lessons::enroll_lesson,
             
             
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // Changes still in the flusher's batching window would be lost otherwise
+            if let tauri::RunEvent::Exit = event {
+                if let Some(db) = app.try_state::<parser::models::KanjiDatabaseState>() {
+                    if let Err(e) = db.flush_now() {
+                        eprintln!("Failed to flush kanji database: {}", e);
+                    }
+                }
+            }
+        });
 }
@@ -0,0 +1,110 @@
+use std::sync::{RwLock, RwLockReadGuard};
+
+use serde::{Deserialize, Serialize};
+
+use super::models::{KanjiDetail, KanjiListing};
+use crate::storage;
+
+pub const SCRAPER_CONFIG_PATH: &str = "scraper_config.json";
+pub const DEFAULT_BASE_URL: &str = "https://www.kanjidamage.com";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ScraperConfig {
+    // Site to scrape, e.g. a mirror or a local stub server
+    pub base_url: String,
+    // http://, https:// or socks5:// proxy URL
+    pub proxy: Option<String>,
+}
+
+impl Default for ScraperConfig {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            proxy: None,
+        }
+    }
+}
+
+impl ScraperConfig {
+    pub fn load() -> Result<Self, String> {
+        Ok(storage::read_json(SCRAPER_CONFIG_PATH)?.unwrap_or_default())
+    }
+
+    // Turn a stored site-relative path back into a full URL on the configured site
+    pub fn resolve(&self, link: &str) -> String {
+        if link.is_empty() || !link.starts_with('/') {
+            return link.to_string();
+        }
+        format!("{}{}", self.base_url.trim_end_matches('/'), link)
+    }
+
+    // Same for the site paths in scraped HTML, like the images in a description. Quoted
+    // attributes only, and "//host" links are left alone.
+    pub fn resolve_html(&self, html: &str) -> String {
+        let base = self.base_url.trim_end_matches('/');
+        let mut resolved = html.to_string();
+
+        for attribute in ["src=\"", "href=\"", "src='", "href='"] {
+            resolved = resolved
+                .split(attribute)
+                .enumerate()
+                .map(|(i, part)| {
+                    if i > 0 && part.starts_with('/') && !part.starts_with("//") {
+                        format!("{}{}", base, part)
+                    } else {
+                        part.to_string()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(attribute);
+        }
+
+        resolved
+    }
+
+    pub fn resolve_listing(&self, mut listing: KanjiListing) -> KanjiListing {
+        listing.map_links(&mut |link: &mut String| *link = self.resolve(link));
+        listing
+    }
+
+    pub fn resolve_detail(&self, mut detail: KanjiDetail) -> KanjiDetail {
+        detail.map_links(&mut |link: &mut String| *link = self.resolve(link));
+
+        detail.description = detail.description.map(|html| self.resolve_html(&html));
+        detail.mnemonic = detail.mnemonic.map(|html| self.resolve_html(&html));
+        detail.breakdown = self.resolve_html(&detail.breakdown);
+        for (_, description) in &mut detail.onyomi {
+            *description = self.resolve_html(description);
+        }
+        detail
+    }
+}
+
+// Strip the scheme and host from a link so stored data doesn't depend on which site it came from
+pub fn to_site_path(link: &str) -> String {
+    match link.split_once("://") {
+        Some((_, rest)) => match rest.find('/') {
+            Some(start) => rest[start..].to_string(),
+            None => "/".to_string(),
+        },
+        None => link.to_string(),
+    }
+}
+
+pub struct ScraperConfigState(RwLock<ScraperConfig>);
+
+impl ScraperConfigState {
+    pub fn new(config: ScraperConfig) -> Self {
+        Self(RwLock::new(config))
+    }
+
+    pub fn read(&self) -> Result<RwLockReadGuard<'_, ScraperConfig>, String> {
+        self.0.read().map_err(|_| "Failed to lock scraper config".to_string())
+    }
+
+    pub fn replace(&self, config: ScraperConfig) -> Result<(), String> {
+        *self.0.write().map_err(|_| "Failed to lock scraper config".to_string())? = config;
+        Ok(())
+    }
+}
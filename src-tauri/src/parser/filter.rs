@@ -0,0 +1,129 @@
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use super::config::ScraperConfigState;
+use super::models::{KanjiDatabase, KanjiDatabaseState, KanjiDetail, KanjiListing};
+use crate::dictionary::kanjidic::{KanjiMetadata, KanjiMetadataMap, KanjiSort, KanjidicState, MetadataFilter};
+use crate::user_data::lists::{StudyLists, StudyListsState};
+
+// Which kanji an operation applies to. Every criterion that's set has to match; usefulness
+// and tags are only known for kanji whose page has been cached.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct KanjiFilter {
+    pub index_from: Option<i32>,
+    pub index_to: Option<i32>,
+    pub is_radical: Option<bool>,
+    pub min_usefulness: Option<u8>,
+    pub tag: Option<String>,
+    pub list: Option<u64>,
+    // Matched against the kanji and its meaning, like search_kanji
+    pub query: Option<String>,
+    #[serde(flatten)]
+    pub metadata: MetadataFilter,
+    pub sort: KanjiSort,
+}
+
+impl KanjiFilter {
+    // Nothing to narrow by, so every kanji matches. A blank query matches everything too.
+    pub fn is_empty(&self) -> bool {
+        self.index_from.is_none()
+            && self.index_to.is_none()
+            && self.is_radical.is_none()
+            && self.min_usefulness.is_none()
+            && self.tag.is_none()
+            && self.list.is_none()
+            && self.query.as_deref().is_none_or(|q| q.trim().is_empty())
+            && self.metadata.is_empty()
+    }
+
+    pub fn matches(
+        &self,
+        listing: &KanjiListing,
+        detail: Option<&KanjiDetail>,
+        metadata: Option<&KanjiMetadata>,
+        lists: &StudyLists
+    ) -> bool {
+        if self.index_from.is_some_and(|from| listing.index < from)
+            || self.index_to.is_some_and(|to| listing.index > to)
+            || self.is_radical.is_some_and(|is_radical| listing.is_radical != is_radical)
+        {
+            return false;
+        }
+
+        if let Some(min_usefulness) = self.min_usefulness {
+            if detail.is_none_or(|d| d.usefulness < min_usefulness) {
+                return false;
+            }
+        }
+
+        if !self.metadata.matches(metadata) {
+            return false;
+        }
+
+        if let Some(tag) = self.tag.as_deref() {
+            if detail.is_none_or(|d| !d.has_tag(tag)) {
+                return false;
+            }
+        }
+
+        if let Some(list) = self.list {
+            let in_list = lists.membership
+                .get(&listing.link)
+                .is_some_and(|member_of| member_of.contains(&list));
+            if !in_list {
+                return false;
+            }
+        }
+
+        if let Some(query) = self.query.as_deref() {
+            let query = query.trim().to_lowercase();
+            if !listing.kanji.contains(&query) && !listing.meaning.to_lowercase().contains(&query) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    // Listings matching the filter, in `sort` order
+    pub fn apply<'a>(
+        &self,
+        db: &'a KanjiDatabase,
+        metadata: &KanjiMetadataMap,
+        lists: &StudyLists
+    ) -> Vec<&'a KanjiListing> {
+        let mut matched: Vec<&KanjiListing> = db.kanjis
+            .iter()
+            .filter(|listing| {
+                let detail = db.detail_by_index(listing.index as u32);
+                self.matches(listing, detail, metadata.get(&listing.link), lists)
+            })
+            .collect();
+
+        matched.sort_by_key(|listing| self.sort.key(listing.index, metadata.get(&listing.link)));
+        matched
+    }
+}
+
+// Search by any combination of criteria, sorted by KANJIDIC2 data when it's been imported
+#[tauri::command]
+pub fn filter_kanji(
+    state: State<'_, KanjiDatabaseState>,
+    scraper_config: State<'_, ScraperConfigState>,
+    kanjidic: State<'_, KanjidicState>,
+    lists: State<'_, StudyListsState>,
+    filter: KanjiFilter
+) -> Result<Vec<KanjiListing>, String> {
+    let db = state.read()?;
+    let metadata = kanjidic.read()?;
+    let lists = lists.read()?;
+    let config = scraper_config.read()?;
+
+    Ok(filter
+        .apply(&db, &metadata, &lists)
+        .into_iter()
+        .cloned()
+        .map(|k| config.resolve_listing(k))
+        .collect())
+}
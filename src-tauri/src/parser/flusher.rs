@@ -0,0 +1,126 @@
+use std::collections::HashSet;
+use std::mem;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::models::KanjiDatabase;
+use super::{KANJI_CHANGELOG_PATH, KANJI_DETAILS_PATH, KANJI_LIST_PATH, KANJI_PAGES_PATH};
+use crate::storage;
+
+// How long to keep collecting changes before writing, so a burst of toggles costs one write
+const FLUSH_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FlushTarget {
+    Listings,
+    Details,
+    Pages,
+    Changelog,
+}
+
+// Write-behind persistence for the in-memory database. Commands mutate the database
+// and mark what changed; a background thread batches the marks and writes the files.
+pub struct Flusher {
+    sender: Sender<()>,
+    db: Arc<RwLock<KanjiDatabase>>,
+    pending: Arc<Pending>,
+}
+
+#[derive(Default)]
+struct Pending {
+    targets: Mutex<HashSet<FlushTarget>>,
+    // Held while writing, so a final flush waits for one that's already under way
+    writing: Mutex<()>,
+}
+
+impl Flusher {
+    pub fn spawn(db: Arc<RwLock<KanjiDatabase>>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let pending = Arc::new(Pending::default());
+        let thread_db = db.clone();
+        let thread_pending = pending.clone();
+        thread::spawn(move || run(thread_db, thread_pending, receiver));
+        Self { sender, db, pending }
+    }
+
+    pub fn mark_dirty(&self, target: FlushTarget) {
+        if let Ok(mut targets) = self.pending.targets.lock() {
+            targets.insert(target);
+        }
+        // The thread only stops once every sender is dropped, so this can't fail while we hold one
+        let _ = self.sender.send(());
+    }
+
+    // Write everything still waiting out the batching window, for when the app is exiting
+    pub fn flush_now(&self) -> Result<(), String> {
+        flush_pending(&self.db, &self.pending)
+    }
+}
+
+fn flush_pending(db: &RwLock<KanjiDatabase>, pending: &Pending) -> Result<(), String> {
+    let _writing = pending.writing.lock().map_err(|_| "Failed to lock flusher".to_string())?;
+    let targets = mem::take(&mut *pending.targets.lock().map_err(|_| "Failed to lock flusher".to_string())?);
+    if targets.is_empty() {
+        return Ok(());
+    }
+    flush(db, &targets)
+}
+
+fn run(db: Arc<RwLock<KanjiDatabase>>, pending: Arc<Pending>, receiver: Receiver<()>) {
+    while receiver.recv().is_ok() {
+        let deadline = Instant::now() + FLUSH_DELAY;
+
+        // Let more marks pile up until the window closes
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match receiver.recv_timeout(remaining) {
+                Ok(()) => {}
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        if let Err(e) = flush_pending(&db, &pending) {
+            eprintln!("Failed to flush kanji database: {}", e);
+        }
+    }
+}
+
+pub fn flush(db: &RwLock<KanjiDatabase>, targets: &HashSet<FlushTarget>) -> Result<(), String> {
+    // Serialize under the read lock, then release it before touching the disk
+    let mut writes = Vec::new();
+    {
+        let db = db.read().map_err(|_| "Failed to lock database".to_string())?;
+
+        if targets.contains(&FlushTarget::Listings) {
+            let json = serde_json::to_string_pretty(&db.kanjis)
+                .map_err(|e| format!("Failed to serialize JSON: {}", e))?;
+            writes.push((KANJI_LIST_PATH, json));
+        }
+
+        if targets.contains(&FlushTarget::Details) {
+            let json = serde_json::to_string_pretty(&db.details)
+                .map_err(|e| format!("Failed to serialize JSON: {}", e))?;
+            writes.push((KANJI_DETAILS_PATH, json));
+        }
+
+        if targets.contains(&FlushTarget::Pages) {
+            let json = serde_json::to_string_pretty(&db.pages)
+                .map_err(|e| format!("Failed to serialize JSON: {}", e))?;
+            writes.push((KANJI_PAGES_PATH, json));
+        }
+
+        if targets.contains(&FlushTarget::Changelog) {
+            let json = serde_json::to_string_pretty(&db.changelog)
+                .map_err(|e| format!("Failed to serialize JSON: {}", e))?;
+            writes.push((KANJI_CHANGELOG_PATH, json));
+        }
+    }
+
+    for (file_path, json) in writes {
+        storage::write_atomic(file_path, &json)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::storage;
+
+pub const HTTP_CONFIG_PATH: &str = "http_config.json";
+
+const USER_AGENT: &str = concat!(
+    "kanjidamage-practice-app/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/Makima-desu/kanjidamage)"
+);
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct HttpConfig {
+    pub timeout_secs: u64,
+    pub max_retries: u32,
+    // Delay before the first retry, doubled on every attempt after that
+    pub backoff_base_ms: u64,
+    pub requests_per_second: f64,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 20,
+            max_retries: 3,
+            backoff_base_ms: 500,
+            requests_per_second: 2.0,
+        }
+    }
+}
+
+impl HttpConfig {
+    pub fn load() -> Result<Self, String> {
+        Ok(storage::read_json(HTTP_CONFIG_PATH)?.unwrap_or_default())
+    }
+
+    fn request_interval(&self) -> Duration {
+        if self.requests_per_second > 0.0 {
+            Duration::from_secs_f64(1.0 / self.requests_per_second)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+// One connection pool shared by every scraper, with retries and a global rate limit
+pub struct HttpClient {
+    client: reqwest::Client,
+    config: HttpConfig,
+    next_slot: Mutex<Instant>,
+}
+
+impl HttpClient {
+    pub fn new(config: HttpConfig, proxy: Option<&str>) -> Result<Self, String> {
+        let mut builder = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(Duration::from_secs(config.timeout_secs));
+
+        if let Some(proxy) = proxy.filter(|p| !p.is_empty()) {
+            let proxy = reqwest::Proxy::all(proxy)
+                .map_err(|e| format!("Invalid proxy {}: {}", proxy, e))?;
+            builder = builder.proxy(proxy);
+        }
+
+        let client = builder
+            .build()
+            .map_err(|e| format!("Failed to build HTTP client: {}", e))?;
+
+        Ok(Self {
+            client,
+            config,
+            next_slot: Mutex::new(Instant::now()),
+        })
+    }
+
+    pub fn config(&self) -> &HttpConfig {
+        &self.config
+    }
+
+    // Wait until the rate limiter hands out the next request slot
+    async fn acquire_slot(&self) {
+        let wait = {
+            let mut next_slot = self.next_slot.lock().await;
+            let now = Instant::now();
+            let slot = (*next_slot).max(now);
+            *next_slot = slot + self.config.request_interval();
+            slot - now
+        };
+
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    // GET with exponential backoff on connection errors, timeouts and 5xx responses
+    pub async fn get(&self, url: &str, headers: HeaderMap) -> Result<reqwest::Response, String> {
+        let mut attempt = 0;
+
+        loop {
+            self.acquire_slot().await;
+
+            let result = self.client
+                .get(url)
+                .headers(headers.clone())
+                .send()
+                .await;
+
+            let retryable = match &result {
+                Ok(response) => response.status().is_server_error(),
+                Err(e) => e.is_connect() || e.is_timeout(),
+            };
+
+            if !retryable || attempt >= self.config.max_retries {
+                return result
+                    .map_err(|e| format!("Failed to fetch {}: {}", url, e));
+            }
+
+            let backoff = self.config.backoff_base_ms.saturating_mul(1 << attempt.min(16));
+            tokio::time::sleep(Duration::from_millis(backoff)).await;
+            attempt += 1;
+        }
+    }
+}
+
+// Error pages would otherwise be parsed like any other page
+pub fn check_status(url: &str, response: reqwest::Response) -> Result<reqwest::Response, String> {
+    let status = response.status();
+    if status.is_success() {
+        Ok(response)
+    } else {
+        Err(format!("Failed to fetch {}: {}", url, status))
+    }
+}
+
+// The client is swapped out wholesale when its configuration changes, so in-flight
+// requests keep the Arc they started with
+pub struct HttpClientState(RwLock<Arc<HttpClient>>);
+
+impl HttpClientState {
+    pub fn new(client: HttpClient) -> Self {
+        Self(RwLock::new(Arc::new(client)))
+    }
+
+    pub fn client(&self) -> Result<Arc<HttpClient>, String> {
+        self.0.read()
+            .map(|client| client.clone())
+            .map_err(|_| "Failed to lock HTTP client".to_string())
+    }
+
+    pub fn replace(&self, client: HttpClient) -> Result<(), String> {
+        *self.0.write().map_err(|_| "Failed to lock HTTP client".to_string())? = Arc::new(client);
+        Ok(())
+    }
+}
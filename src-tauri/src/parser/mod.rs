@@ -1,40 +1,118 @@
+pub mod config;
+pub mod filter;
+pub mod flusher;
+pub mod http;
 pub mod models;
-use std::fs::{self, File};
-use std::io::{self, BufRead, Write};
-use std::path::Path;
+pub mod refresh;
+pub mod tags;
+use std::collections::BTreeMap;
 use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
 use scraper::selector::CssLocalName;
 use scraper::{Element, Node};
 use rand::seq::SliceRandom;
 
 
-use models::{CommandError, Component, Jukugo, KanjiDatabaseState, KanjiDetail, KanjiListing, KunyomiEntry, Lookalike, PracticeItem, SynonymEntry, Tag, UsedIn};
+use config::{to_site_path, ScraperConfig, ScraperConfigState, SCRAPER_CONFIG_PATH};
+use flusher::FlushTarget;
+use http::{check_status, HttpClient, HttpClientState, HttpConfig, HTTP_CONFIG_PATH};
+use models::{ChangelogEntry, CommandError, Component, Jukugo, KanjiDatabase, KanjiDatabaseState, KanjiDetail, KanjiId, KanjiListing, KunyomiEntry, Lookalike, PageMeta, PracticeItem, SynonymEntry, Tag, UsedIn};
 use scraper::{CaseSensitivity, ElementRef, Html, Selector};
+use reqwest::header::HeaderMap;
 use tauri::State;
 
+use crate::user_data::history::{Change, HistoryState};
+use crate::user_data::notes::NotesState;
+use crate::{practice, storage};
+
+pub const KANJI_LIST_PATH: &str = "kanji_list.json";
+pub const KANJI_DETAILS_PATH: &str = "kanji_details.json";
+pub const KANJI_PAGES_PATH: &str = "kanji_pages.json";
+pub const KANJI_CHANGELOG_PATH: &str = "kanji_changelog.json";
+
+// Load the kanji list and cached details from disk, scraping the list if there's no local copy yet
+pub async fn load_kanji_database(client: &HttpClient, config: &ScraperConfig) -> Result<KanjiDatabase, String> {
+    let kanjis = match load_cache(KANJI_LIST_PATH, strip_listing_origins)? {
+        Some(kanjis) => kanjis,
+        None => {
+            let kanjis = scrape_kanji_list(client, config).await?;
+            storage::write_json(KANJI_LIST_PATH, &kanjis)?;
+            kanjis
+        }
+    };
+
+    let details = load_cache(KANJI_DETAILS_PATH, strip_detail_origins)?.unwrap_or_default();
+    let pages = load_cache(KANJI_PAGES_PATH, |_: &mut BTreeMap<KanjiId, PageMeta>| {})?.unwrap_or_default();
+    let changelog = load_cache(KANJI_CHANGELOG_PATH, |_: &mut Vec<ChangelogEntry>| {})?.unwrap_or_default();
+
+    Ok(KanjiDatabase { kanjis, details, pages, changelog })
+}
+
+// Read a cache file and bring it up to the current format. Older caches keyed details by
+// URL segment and mixed absolute and relative links; parsing them into KanjiIds and site
+// paths upgrades them, and the file is rewritten once if that changed anything.
+fn load_cache<T: Serialize + DeserializeOwned>(file_path: &str, normalize: impl FnOnce(&mut T)) -> Result<Option<T>, String> {
+    let Some(raw) = storage::read_json::<Value>(file_path)? else {
+        return Ok(None);
+    };
+
+    let mut cache: T = serde_json::from_value(raw.clone())
+        .map_err(|e| format!("Failed to parse JSON in {}: {}", file_path, e))?;
+    normalize(&mut cache);
+
+    let migrated = serde_json::to_value(&cache)
+        .map_err(|e| format!("Failed to serialize JSON for {}: {}", file_path, e))?;
+    if migrated != raw {
+        storage::write_json(file_path, &cache)?;
+    }
+
+    Ok(Some(cache))
+}
+
+fn strip_origin(link: &mut String) {
+    *link = to_site_path(link);
+}
+
+fn strip_listing_origins(kanjis: &mut Vec<KanjiListing>) {
+    for listing in kanjis {
+        listing.map_links(&mut strip_origin);
+    }
+}
+
+fn strip_detail_origins(details: &mut BTreeMap<KanjiId, KanjiDetail>) {
+    for detail in details.values_mut() {
+        detail.map_links(&mut strip_origin);
+    }
+}
+
 #[tauri::command]
-pub async fn fetch_and_save_kanji_list() -> Result<Vec<KanjiListing>, String> {
-    let file_path = "kanji_list.json";
-    
-    // Check if file exists
-    if Path::new(file_path).exists() {
-        // Read and parse existing file
-        return match fs::read_to_string(file_path) {
-            Ok(contents) => {
-                serde_json::from_str(&contents)
-                    .map_err(|e| format!("Failed to parse JSON: {}", e))
-            }
-            Err(e) => Err(format!("Failed to read file: {}", e))
-        };
+pub async fn fetch_and_save_kanji_list(
+    state: State<'_, KanjiDatabaseState>,
+    http: State<'_, HttpClientState>,
+    scraper_config: State<'_, ScraperConfigState>
+) -> Result<Vec<KanjiListing>, String> {
+    let config = scraper_config.read()?.clone();
+    {
+        let db = state.read()?;
+        if !db.kanjis.is_empty() {
+            return Ok(db.kanjis.iter().cloned().map(|k| config.resolve_listing(k)).collect());
+        }
     }
-    
-    // If file doesn't exist, fetch from web
-    let client = reqwest::Client::new();
-    let response = client
-        .get("https://www.kanjidamage.com/kanji")
-        .send()
-        .await
-        .map_err(|e| format!("Failed to fetch data: {}", e))?
+
+    let client = http.client()?;
+    let kanjis = scrape_kanji_list(&client, &config).await?;
+    state.write()?.kanjis = kanjis.clone();
+    state.mark_dirty(&[FlushTarget::Listings]);
+
+    Ok(kanjis.into_iter().map(|k| config.resolve_listing(k)).collect())
+}
+
+// Scrape the full kanji index from the site
+pub async fn scrape_kanji_list(client: &HttpClient, config: &ScraperConfig) -> Result<Vec<KanjiListing>, String> {
+    let url = config.resolve("/kanji");
+    let response = check_status(&url, client.get(&url, HeaderMap::new()).await?)?
         .text()
         .await
         .map_err(|e| format!("Failed to get response text: {}", e))?;
@@ -63,30 +141,28 @@ pub async fn fetch_and_save_kanji_list() -> Result<Vec<KanjiListing>, String> {
                 let l = tds[2].select(&link_selector)
                     .next()
                     .and_then(|a| a.value().attr("href"))
-                    .map(|href| format!("https://www.kanjidamage.com{}", href))
-                    .unwrap_or_default();
+                    .and_then(KanjiId::parse);
                 (k, l, false)
             } else if let Some(img) = tds[2].select(&img_selector).next() {
                 let src = img.value().attr("src").unwrap_or_default();
                 let l = tds[2].select(&link_selector)
                     .next()
                     .and_then(|a| a.value().attr("href"))
-                    .map(|href| format!("https://www.kanjidamage.com{}", href))
-                    .unwrap_or_default();
-                (format!("https://www.kanjidamage.com{}", src), l, true)
+                    .and_then(KanjiId::parse);
+                (src.to_string(), l, true)
             } else {
                 let k = tds[2].text().collect::<String>().trim().to_string();
                 let l = tds[2].select(&link_selector)
                     .next()
                     .and_then(|a| a.value().attr("href"))
-                    .map(|href| format!("https://www.kanjidamage.com{}", href))
-                    .unwrap_or_default();
+                    .and_then(KanjiId::parse);
                 (k, l, false)
             };
             
             let meaning = tds[3].text().next().unwrap_or("").trim().to_string();
+            let Some(link) = link else { continue };
             
-            if !meaning.is_empty() && !link.is_empty() {
+            if !meaning.is_empty() {
                 entries.push(KanjiListing {
                     index,
                     kanji,
@@ -99,127 +175,116 @@ pub async fn fetch_and_save_kanji_list() -> Result<Vec<KanjiListing>, String> {
             }
         }
     }
-    
-    // Save to file
-    let json = serde_json::to_string_pretty(&entries)
-        .map_err(|e| format!("Failed to serialize JSON: {}", e))?;
-    File::create(file_path)
-        .map_err(|e| format!("Failed to create file: {}", e))?
-        .write_all(json.as_bytes())
-        .map_err(|e| format!("Failed to write file: {}", e))?;
-    
+
+    // Don't let a page without the index table wipe the saved list
+    if entries.is_empty() {
+        return Err(format!("No kanji found on {}", url));
+    }
     Ok(entries)
 }
 
 
 #[tauri::command]
-pub async fn get_kanji_list() -> Result<Vec<KanjiListing>, String> {
-    // Read the JSON file
-    let file_content = std::fs::read_to_string("kanji_list.json")
-        .map_err(|e| e.to_string())?;
-    
-    // Parse the JSON content
-    let kanji_list: Vec<KanjiListing> = serde_json::from_str(&file_content)
-        .map_err(|e| e.to_string())?;
-    
-    Ok(kanji_list)
-}
-
-pub fn save_kanji_data(kanji_id: &str, kanji_detail: &KanjiDetail) -> Result<(), String> {
-    let file_path = "kanji_details.json";
-    let mut kanji_map = if Path::new(file_path).exists() {
-        let content = fs::read_to_string(file_path)
-            .map_err(|e| format!("Failed to read file: {}", e))?;
-        serde_json::from_str(&content)
-            .map_err(|e| format!("Failed to parse JSON: {}", e))?
-    } else {
-        serde_json::Map::new()
-    };
-    
-    kanji_map.insert(
-        kanji_id.to_string(),
-        serde_json::to_value(kanji_detail)
-            .map_err(|e| format!("Failed to serialize kanji: {}", e))?
-    );
-    
-    let json = serde_json::to_string_pretty(&kanji_map)
-        .map_err(|e| format!("Failed to serialize JSON: {}", e))?;
-        
-    fs::write(file_path, json)
-        .map_err(|e| format!("Failed to write file: {}", e))?;
-    
-    Ok(())
+pub async fn get_kanji_list(
+    state: State<'_, KanjiDatabaseState>,
+    scraper_config: State<'_, ScraperConfigState>
+) -> Result<Vec<KanjiListing>, String> {
+    let config = scraper_config.read()?;
+    Ok(state.read()?.kanjis.iter().cloned().map(|k| config.resolve_listing(k)).collect())
 }
 
 #[tauri::command]
-pub async fn get_kanji(url: String) -> Result<KanjiDetail, String> {
-    let file_path = "kanji_details.json";
-    let kanji_id = &url.split('/').last().unwrap_or_default();
-    
-    // Try to read from local file first
-    if Path::new(file_path).exists() {
-        let file_content = fs::read_to_string(file_path)
-            .map_err(|e| format!("Failed to read file: {}", e))?;
-            
-        let kanji_map: serde_json::Map<String, serde_json::Value> = 
-            serde_json::from_str(&file_content)
-            .map_err(|e| format!("Failed to parse JSON: {}", e))?;
-            
-        // Check if kanji exists in the map
-        if let Some(kanji_value) = kanji_map.get(kanji_id.clone()) {
-            return serde_json::from_value(kanji_value.clone())
-                .map_err(|e| format!("Failed to parse kanji data: {}", e));
-        }
+pub async fn get_kanji(
+    state: State<'_, KanjiDatabaseState>,
+    http: State<'_, HttpClientState>,
+    scraper_config: State<'_, ScraperConfigState>,
+    url: String
+) -> Result<KanjiDetail, String> {
+    let kanji_id = KanjiId::parse(&url).ok_or_else(|| format!("Invalid kanji link: {}", url))?;
+    let config = scraper_config.read()?.clone();
+    
+    // Serve from the in-memory cache first
+    if let Some(kanji_detail) = state.read()?.detail(&kanji_id).cloned() {
+        return Ok(config.resolve_detail(kanji_detail));
     }
 
     // If not found in cache, scrape and save
-    let kanji_detail = fetch_kanji(url.clone()).await?;
-    save_kanji_data(kanji_id, &kanji_detail)?;
-    
-    Ok(kanji_detail)
+    let client = http.client()?;
+    let kanji_detail = refresh::refresh_page(&state, &client, &config, &kanji_id).await?;
+    Ok(config.resolve_detail(kanji_detail))
 }
 
-// New function for manual refresh
+// Manual refresh, only re-parses pages the site actually changed
 #[tauri::command]
-pub async fn refresh_kanji_data(url: String) -> Result<KanjiDetail, String> {
-    let kanji_id = url.split('/').last().unwrap_or_default();
-    let kanji_detail = fetch_kanji(url.clone()).await?;
-    save_kanji_data(kanji_id, &kanji_detail)?;
-    Ok(kanji_detail)
+pub async fn refresh_kanji_data(
+    state: State<'_, KanjiDatabaseState>,
+    http: State<'_, HttpClientState>,
+    scraper_config: State<'_, ScraperConfigState>,
+    url: String
+) -> Result<KanjiDetail, String> {
+    let kanji_id = KanjiId::parse(&url).ok_or_else(|| format!("Invalid kanji link: {}", url))?;
+    let config = scraper_config.read()?.clone();
+    let client = http.client()?;
+    let kanji_detail = refresh::refresh_page(&state, &client, &config, &kanji_id).await?;
+    Ok(config.resolve_detail(kanji_detail))
 }
 
 #[tauri::command]
-pub async fn fetch_kanji(url: String) -> Result<KanjiDetail, String> {
-    let file_path = "kanji_details.json";
-    let kanji_id = url.split('/').last().unwrap_or_default();
-    
-    // Get existing practice status if available
-    let existing_practice = if Path::new(file_path).exists() {
-        let file_content = fs::read_to_string(file_path)
-            .map_err(|e| format!("Failed to read file: {}", e))?;
-            
-        let kanji_map: serde_json::Map<String, serde_json::Value> = 
-            serde_json::from_str(&file_content)
-            .map_err(|e| format!("Failed to parse JSON: {}", e))?;
-            
-        kanji_map.get(kanji_id)
-            .and_then(|value| value.get("practice"))
-            .and_then(|practice| practice.as_bool())
-            .unwrap_or(false)
-    } else {
-        false
-    };
+pub fn get_http_config(http: State<'_, HttpClientState>) -> Result<HttpConfig, String> {
+    Ok(http.client()?.config().clone())
+}
 
-    let client = reqwest::Client::new();
-    let response = client.get(&url)
-        .send()
-        .await
-        .map_err(|e| e.to_string())?
-        .text()
-        .await
-        .map_err(|e| e.to_string())?;
-        
-    let document = Html::parse_document(&response);
+// Rebuild the shared client with new settings and remember them for the next launch
+#[tauri::command]
+pub fn update_http_config(
+    http: State<'_, HttpClientState>,
+    scraper_config: State<'_, ScraperConfigState>,
+    config: HttpConfig
+) -> Result<(), String> {
+    let client = HttpClient::new(config.clone(), scraper_config.read()?.proxy.as_deref())?;
+    storage::write_json(HTTP_CONFIG_PATH, &config)?;
+    http.replace(client)
+}
+
+#[tauri::command]
+pub fn get_scraper_config(scraper_config: State<'_, ScraperConfigState>) -> Result<ScraperConfig, String> {
+    Ok(scraper_config.read()?.clone())
+}
+
+// Switch the site or proxy used for scraping. Stored links are site-relative, so cached
+// data follows the new base URL without being rewritten.
+#[tauri::command]
+pub fn update_scraper_config(
+    http: State<'_, HttpClientState>,
+    scraper_config: State<'_, ScraperConfigState>,
+    config: ScraperConfig
+) -> Result<(), String> {
+    let http_config = http.client()?.config().clone();
+    let client = HttpClient::new(http_config, config.proxy.as_deref())?;
+    storage::write_json(SCRAPER_CONFIG_PATH, &config)?;
+    http.replace(client)?;
+    scraper_config.replace(config)
+}
+
+// Changes the site made to cached pages, newest first
+#[tauri::command]
+pub fn get_kanji_changelog(
+    state: State<'_, KanjiDatabaseState>,
+    kanji_id: Option<String>
+) -> Result<Vec<ChangelogEntry>, String> {
+    let kanji_id = kanji_id.as_deref().and_then(KanjiId::parse);
+    let db = state.read()?;
+    Ok(db.changelog
+        .iter()
+        .rev()
+        .filter(|entry| kanji_id.as_ref().is_none_or(|id| &entry.kanji_id == id))
+        .cloned()
+        .collect())
+}
+
+// Build a KanjiDetail from the HTML of a single kanji page
+pub fn parse_kanji_page(kanji_id: &KanjiId, html: &str) -> KanjiDetail {
+    let document = Html::parse_document(html);
 
     let index = document
         .select(&Selector::parse("div.col-md-8.text-centered").unwrap())
@@ -484,7 +549,7 @@ pub async fn fetch_kanji(url: String) -> Result<KanjiDetail, String> {
                                     components.push(Component {
                                         kanji: current_kanji.clone(),
                                         meaning: meaning.to_string(),
-                                        href: format!("https://www.kanjidamage.com{}", current_href.clone()),
+                                        href: KanjiId::parse(&current_href),
                                         image_src: None,
                                     });
                                     current_kanji.clear();
@@ -538,13 +603,13 @@ pub async fn fetch_kanji(url: String) -> Result<KanjiDetail, String> {
                 a.select(&Selector::parse("img").unwrap())
                     .next()
                     .and_then(|img| img.value().attr("src"))
-                    .map(|src| format!("https://www.kanjidamage.com{}", src))
+                    .map(String::from)
                     .unwrap_or_default()
             };
             
             UsedIn {
                 kanji,
-                link: a.value().attr("href").unwrap_or_default().to_string()
+                link: a.value().attr("href").and_then(KanjiId::parse)
             }
         })
         .collect::<Vec<UsedIn>>();
@@ -580,13 +645,13 @@ pub async fn fetch_kanji(url: String) -> Result<KanjiDetail, String> {
     .select(&Selector::parse("div.navigation-header div.col-md-2 a").unwrap())
     .next()
     .and_then(|a| a.value().attr("href"))
-    .map(|href| href.to_string());
+    .and_then(KanjiId::parse);
 
     let next_link = document
         .select(&Selector::parse("div.navigation-header div.col-md-2.text-righted a").unwrap())
         .next()
         .and_then(|a| a.value().attr("href"))
-        .map(|href| href.to_string());
+        .and_then(KanjiId::parse);
 
     let lookalikes = document
         .select(&Selector::parse("table.table tr").unwrap())
@@ -600,15 +665,14 @@ pub async fn fetch_kanji(url: String) -> Result<KanjiDetail, String> {
                     
                 let kanji_link = kanji_anchor
                     .and_then(|a| a.value().attr("href"))
-                    .map(|href| format!("https://www.kanjidamage.com{}", href))
-                    .unwrap_or_default();
+                    .and_then(KanjiId::parse);
     
                 let kanji = kanji_anchor
                     .map(|a| {
                         a.select(&Selector::parse("img").unwrap())
                             .next()
                             .and_then(|img| img.value().attr("src"))
-                            .map(|src| format!("https://www.kanjidamage.com{}", src))
+                            .map(String::from)
                             .unwrap_or_else(|| a.text().collect::<String>())
                     })
                     .unwrap_or_default();
@@ -622,15 +686,14 @@ pub async fn fetch_kanji(url: String) -> Result<KanjiDetail, String> {
                         a.select(&Selector::parse("img").unwrap())
                             .next()
                             .and_then(|img| img.value().attr("src"))
-                            .map(|src| format!("https://www.kanjidamage.com{}", src))
+                            .map(String::from)
                             .unwrap_or_else(|| a.text().collect::<String>())
                     })
                     .unwrap_or_default();
     
                 let radical_link = radical_anchor
                     .and_then(|a| a.value().attr("href"))
-                    .map(|href| format!("https://www.kanjidamage.com{}", href))
-                    .unwrap_or_default();
+                    .and_then(KanjiId::parse);
     
                 Some(Lookalike {
                     kanji,
@@ -646,218 +709,139 @@ pub async fn fetch_kanji(url: String) -> Result<KanjiDetail, String> {
         })
         .collect::<Vec<Lookalike>>();
 
-        let kanji_detail = KanjiDetail {
-            index,
-            link: url.clone(),
-            kanji,
-            meanings,
-            tags,
-            description,
-            onyomi,
-            kunyomi,
-            jukugo,
-            mnemonic,
-            usefulness: stars,
-            used_in,
-            synonyms,
-            prev_link,
-            next_link,
-            breakdown,
-            lookalikes,
-            practice: existing_practice
-        };
-        
-        
-    Ok(kanji_detail)
+    KanjiDetail {
+        index,
+        link: kanji_id.clone(),
+        kanji,
+        meanings,
+        tags,
+        description,
+        onyomi,
+        kunyomi,
+        jukugo,
+        mnemonic,
+        usefulness: stars,
+        used_in,
+        synonyms,
+        prev_link,
+        next_link,
+        breakdown,
+        lookalikes,
+        // Owned by the listing, filled in when the page is stored
+        practice: false
+    }
+}
+
+// Kanji linked from the breakdown next to the heading, i.e. the ones this kanji is built from
+pub fn breakdown_components(breakdown: &str) -> Vec<KanjiId> {
+    let fragment = Html::parse_fragment(breakdown);
+    let link_selector = Selector::parse("a[href]").unwrap();
+
+    let mut components = Vec::new();
+    for href in fragment.select(&link_selector).filter_map(|a| a.value().attr("href")) {
+        if !href.contains("/kanji/") {
+            continue;
+        }
+        if let Some(kanji_id) = KanjiId::parse(href) {
+            if !components.iter().any(|c: &KanjiId| c.index == kanji_id.index) {
+                components.push(kanji_id);
+            }
+        }
+    }
+
+    components
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn search_kanji(
     state: State<'_, KanjiDatabaseState>,
+    scraper_config: State<'_, ScraperConfigState>,
+    notes: State<'_, NotesState>,
     index: Option<i32>,
     kanji: Option<String>,
-    meaning: Option<String>
+    meaning: Option<String>,
+    note: Option<String>,
+    tag: Option<String>
 ) -> Result<Vec<KanjiListing>, String> {
-    let db = state.0.lock().map_err(|_| "Failed to lock database".to_string())?;
-    let results = db.search(
+    let db = state.read()?;
+    let has_query = index.is_some() || kanji.is_some() || meaning.is_some() || note.is_some();
+    let mut results = db.search(
         index,
         kanji.as_deref(),
         meaning.as_deref()
     );
-    
-    Ok(results.into_iter().cloned().collect())
-}
 
-#[tauri::command]
-pub fn update_kanji_practice(index: u32, practice: bool) -> Result<(), String> {
-    let file_paths = ["kanji_details.json", "kanji_list.json"];
-
-    for file_path in file_paths.iter() {
-        let file_content = fs::read_to_string(file_path)
-            .map_err(|e| format!("Failed to read file {}: {}", file_path, e))?;
-
-        if *file_path == "kanji_list.json" {
-            // Handle array structure
-            let mut kanji_list: Vec<serde_json::Value> = 
-                serde_json::from_str(&file_content)
-                .map_err(|e| format!("Failed to parse JSON in {}: {}", file_path, e))?;
-
-            // Find and update the kanji in the array
-            if let Some(kanji) = kanji_list.iter_mut().find(|k| {
-                k.get("index")
-                    .and_then(|i| i.as_u64())
-                    .map_or(false, |i| i as u32 == index)
-            }) {
-                if let Some(obj) = kanji.as_object_mut() {
-                    obj.insert(
-                        "practice".to_string(),
-                        serde_json::Value::Bool(practice)
-                    );
-
-                    let updated_content = serde_json::to_string_pretty(&kanji_list)
-                        .map_err(|e| format!("Failed to serialize JSON in {}: {}", file_path, e))?;
-
-                    fs::write(file_path, updated_content)
-                        .map_err(|e| format!("Failed to write file {}: {}", file_path, e))?;
+    // Also match the user's own mnemonics, notes and tags
+    if let Some(query) = note.as_deref() {
+        for kanji_id in notes.read()?.iter().filter(|(_, n)| n.matches(query)).map(|(id, _)| id) {
+            if let Some(listing) = KanjiListing::search_by_index(&db.kanjis, kanji_id.index as i32) {
+                if !results.iter().any(|r| r.index == listing.index) {
+                    results.push(listing);
                 }
             }
-        } else {
-            // Handle object map structure (original logic)
-            let mut kanji_map: serde_json::Map<String, serde_json::Value> = 
-                serde_json::from_str(&file_content)
-                .map_err(|e| format!("Failed to parse JSON in {}: {}", file_path, e))?;
-
-            let kanji_key = kanji_map.iter()
-                .find(|(_, value)| {
-                    value.get("index")
-                        .and_then(|i| i.as_u64())
-                        .map_or(false, |i| i as u32 == index)
-                })
-                .map(|(key, _)| key.clone())
-                .ok_or_else(|| format!("Kanji with index {} not found in {}", index, file_path))?;
-
-            if let Some(kanji_value) = kanji_map.get_mut(&kanji_key) {
-                if let Some(obj) = kanji_value.as_object_mut() {
-                    obj.insert(
-                        "practice".to_string(),
-                        serde_json::Value::Bool(practice)
-                    );
+        }
+    }
 
-                    let updated_content = serde_json::to_string_pretty(&kanji_map)
-                        .map_err(|e| format!("Failed to serialize JSON in {}: {}", file_path, e))?;
+    // A tag narrows the other criteria down, or lists everything tagged when it's the only one
+    if let Some(tag) = tag.as_deref() {
+        let tagged = |listing: &KanjiListing| {
+            db.detail_by_index(listing.index as u32).is_some_and(|d| d.has_tag(tag))
+        };
+        if has_query {
+            results.retain(|listing| tagged(listing));
+        } else {
+            results = db.kanjis.iter().filter(|listing| tagged(listing)).collect();
+        }
+    }
+    
+    let config = scraper_config.read()?;
+    Ok(results.into_iter().cloned().map(|k| config.resolve_listing(k)).collect())
+}
 
-                    fs::write(file_path, updated_content)
-                        .map_err(|e| format!("Failed to write file {}: {}", file_path, e))?;
-                }
-            }
+#[tauri::command]
+pub fn update_kanji_practice(
+    state: State<'_, KanjiDatabaseState>,
+    history: State<'_, HistoryState>,
+    index: u32,
+    practice: bool
+) -> Result<(), String> {
+    {
+        let mut db = state.write()?;
+        let current = KanjiListing::search_by_index(&db.kanjis, index as i32)
+            .ok_or_else(|| format!("Kanji with index {} not found", index))?
+            .practice;
+        if current == practice {
+            return Ok(());
         }
+        db.set_practice(index, practice)?;
     }
+    state.mark_dirty(&[FlushTarget::Listings, FlushTarget::Details]);
 
-    Ok(())
+    let description = if practice {
+        format!("Add kanji #{} to practice", index)
+    } else {
+        format!("Remove kanji #{} from practice", index)
+    };
+    history.record(description, Change::Practice { indexes: vec![index], practice })
 }
 
 #[tauri::command]
-pub fn initialize_practice_pool() -> Result<Vec<KanjiDetail>, String> {
-    let file_path = "kanji_details.json";
-    
-    let file_content = fs::read_to_string(file_path)
-        .map_err(|e| format!("Failed to read file: {}", e))?;
-    
-    let kanji_map: serde_json::Map<String, serde_json::Value> = 
-        serde_json::from_str(&file_content)
-        .map_err(|e| format!("Failed to parse JSON: {}", e))?;
-    
-    let mut practice_pool: Vec<KanjiDetail> = kanji_map
-        .values()
-        .filter_map(|value| {
-            if value.get("practice").and_then(|p| p.as_bool()).unwrap_or(false) {
-                // Extract and clean onyomi readings
-                let onyomi = value.get("onyomi")
-                    .and_then(|o| o.as_array())
-                    .map(|arr| {
-                        arr.iter()
-                            .filter_map(|entry| {
-                                entry.as_array().and_then(|pair| {
-                                    pair.get(0).and_then(|reading| {
-                                        reading.as_str().map(|s| {
-                                            // Split the string by comma and trim each part
-                                            let readings = s.split(',')
-                                                .map(|r| r.trim().to_string())
-                                                .filter(|r| !r.is_empty())
-                                                .collect::<Vec<_>>();
-                                            
-                                            // Create tuples for each reading
-                                            readings.into_iter()
-                                                .map(|r| (r, String::new()))
-                                                .collect::<Vec<_>>()
-                                        })
-                                    })
-                                })
-                            })
-                            .flatten()
-                            .collect::<Vec<_>>()
-                    })
-                    .unwrap_or_default();
-
-                // Parse the full KanjiDetail structure
-                Some(KanjiDetail {
-                    index: value.get("index").and_then(|i| i.as_u64()).unwrap_or(0) as u32,
-                    kanji: value.get("kanji").and_then(|k| k.as_str()).unwrap_or("").to_string(),
-                    link: value.get("link").and_then(|l| l.as_str()).unwrap_or("").to_string(), 
-                    meanings: value.get("meanings")
-                    .and_then(|m| m.as_array())
-                    .map(|arr| {
-                        arr.iter()
-                            .filter_map(|v| v.as_str())
-                            .map(|s| s.to_string())
-                            .collect::<Vec<String>>()
-                    })
-                    .unwrap_or_default(),
-                    tags: value.get("tags")
-                        .and_then(|t| serde_json::from_value(t.clone()).ok())
-                        .unwrap_or_default(),
-                    description: value.get("description")
-                        .and_then(|d| d.as_str())
-                        .map(|s| s.to_string()),
-                    onyomi,
-                    kunyomi: value.get("kunyomi")
-                        .and_then(|k| serde_json::from_value(k.clone()).ok())
-                        .unwrap_or_default(),
-                    jukugo: value.get("jukugo")
-                        .and_then(|j| serde_json::from_value(j.clone()).ok())
-                        .unwrap_or_default(),
-                    mnemonic: value.get("mnemonic")
-                        .and_then(|m| m.as_str())
-                        .map(|s| s.to_string()),
-                    usefulness: value.get("usefulness")
-                        .and_then(|u| u.as_u64())
-                        .unwrap_or(0) as u8,
-                    used_in: value.get("used_in")
-                        .and_then(|u| serde_json::from_value(u.clone()).ok())
-                        .unwrap_or_default(),
-                    synonyms: value.get("synonyms")
-                        .and_then(|s| serde_json::from_value(s.clone()).ok())
-                        .unwrap_or_default(),
-                    prev_link: value.get("prev_link")
-                        .and_then(|p| p.as_str())
-                        .map(|s| s.to_string()),
-                    next_link: value.get("next_link")
-                        .and_then(|n| n.as_str())
-                        .map(|s| s.to_string()),
-                    breakdown: value.get("breakdown")
-                        .and_then(|b| b.as_str())
-                        .unwrap_or("")
-                        .to_string(),
-                    lookalikes: value.get("lookalikes")
-                        .and_then(|l| serde_json::from_value(l.clone()).ok())
-                        .unwrap_or_default(),
-                    practice: value.get("practice")
-                        .and_then(|p| p.as_bool())
-                        .unwrap_or(false),
-                })
-            } else {
-                None
-            }
+pub fn initialize_practice_pool(
+    state: State<'_, KanjiDatabaseState>,
+    scraper_config: State<'_, ScraperConfigState>
+) -> Result<Vec<KanjiDetail>, String> {
+    let config = scraper_config.read()?;
+    let mut practice_pool: Vec<KanjiDetail> = state.read()?
+        .practice_pool()
+        .into_iter()
+        .map(|mut detail| {
+            // Split combined readings and drop the mnemonic descriptions
+            detail.onyomi = practice::onyomi_readings(&detail)
+                .into_iter()
+                .map(|r| (r, String::new()))
+                .collect();
+            config.resolve_detail(detail)
         })
         .collect();
     
@@ -868,3 +852,105 @@ pub fn initialize_practice_pool() -> Result<Vec<KanjiDetail>, String> {
     Ok(practice_pool)
 }
 
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+
+    use super::*;
+
+    // A cache file of its own per test, tests run in parallel
+    fn write_cache(name: &str, value: &Value) -> String {
+        let path = std::env::temp_dir().join(format!("kd-cache-test-{}-{}.json", std::process::id(), name));
+        fs::write(&path, value.to_string()).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn legacy_detail() -> Value {
+        json!({
+            "index": 1,
+            "link": "https://www.kanjidamage.com/kanji/1-one-line-radical-%E4%B8%80",
+            "kanji": "一",
+            "meanings": ["one"],
+            "tags": [{ "name": "KUNKUN", "link": "https://www.kanjidamage.com/tags/kunkun" }],
+            "description": null,
+            "onyomi": [],
+            "kunyomi": [],
+            "jukugo": [],
+            "mnemonic": null,
+            "usefulness": 5,
+            "used_in": [
+                { "kanji": "子", "link": "/kanji/5-child-%E5%AD%90" },
+                { "kanji": "https://www.kanjidamage.com/assets/dizzy.jpg", "link": "https://www.kanjidamage.com/kanji/252-dizzy/" }
+            ],
+            "synonyms": [],
+            "prev_link": "",
+            "next_link": "/kanji/2-two-%E4%BA%8C",
+            "breakdown": "",
+            "lookalikes": [],
+            "practice": false
+        })
+    }
+
+    #[test]
+    fn legacy_details_are_rekeyed_and_rewritten() {
+        let path = write_cache("details", &json!({ "1-one-line-radical-%E4%B8%80": legacy_detail() }));
+
+        let details = load_cache(&path, strip_detail_origins).unwrap().unwrap();
+        let detail = &details[&KanjiId::parse("/kanji/1").unwrap()];
+        assert_eq!(detail.link.to_string(), "/kanji/1-one-line-radical-%E4%B8%80");
+        assert_eq!(detail.tags[0].link, "/tags/kunkun");
+        assert_eq!(detail.used_in[1].kanji, "/assets/dizzy.jpg");
+        assert_eq!(detail.used_in[1].link.as_ref().unwrap().to_string(), "/kanji/252-dizzy");
+        assert!(detail.prev_link.is_none());
+        assert_eq!(detail.next_link.as_ref().unwrap().index, 2);
+
+        // The file now holds the current format, so loading it again changes nothing
+        let rewritten: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(rewritten.get("/kanji/1-one-line-radical-%E4%B8%80").is_some());
+        assert_eq!(rewritten["/kanji/1-one-line-radical-%E4%B8%80"]["link"], "/kanji/1-one-line-radical-%E4%B8%80");
+        load_cache(&path, strip_detail_origins).unwrap().unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&fs::read_to_string(&path).unwrap()).unwrap(), rewritten);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn legacy_listings_keep_text_kanji_and_strip_image_origins() {
+        let path = write_cache("listings", &json!([
+            {
+                "index": 1, "kanji": "一", "meaning": "one", "is_radical": true,
+                "link": "https://www.kanjidamage.com/kanji/1-one-line-radical-%E4%B8%80",
+                "has_image": false, "practice": true
+            },
+            {
+                "index": 252, "kanji": "https://www.kanjidamage.com/assets/dizzy.jpg", "meaning": "dizzy",
+                "is_radical": true, "link": "/kanji/252-dizzy", "has_image": true, "practice": false
+            }
+        ]));
+
+        let kanjis = load_cache(&path, strip_listing_origins).unwrap().unwrap();
+        assert_eq!(kanjis[0].kanji, "一");
+        assert_eq!(kanjis[0].link.to_string(), "/kanji/1-one-line-radical-%E4%B8%80");
+        assert_eq!(kanjis[1].kanji, "/assets/dizzy.jpg");
+        assert!(kanjis[0].practice);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn current_caches_are_left_alone() {
+        let detail: KanjiDetail = serde_json::from_value(legacy_detail()).unwrap();
+        let mut details = BTreeMap::from([(detail.link.clone(), detail)]);
+        strip_detail_origins(&mut details);
+        let current = serde_json::to_value(&details).unwrap();
+        let path = write_cache("current", &current);
+        let written = fs::read_to_string(&path).unwrap();
+
+        load_cache(&path, strip_detail_origins).unwrap().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), written);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
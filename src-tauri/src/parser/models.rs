@@ -1,8 +1,12 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::{Map, Value};
-use std::{collections::HashSet, sync::Mutex};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
-use super::get_kanji_list;
+use super::flusher::{FlushTarget, Flusher};
 
 
 #[derive(Debug, serde::Serialize)]
@@ -18,18 +22,119 @@ impl From<std::io::Error> for CommandError {
     }
 }
 
+// Canonical identity of a kanji page, e.g. index 1 and slug "one-line-radical-%E4%B8%80".
+// Stored and sent to the frontend as the site path "/kanji/1-one-line-radical-%E4%B8%80",
+// but parsed from full URLs and bare "1-one-..." ids too so older caches still load.
+// Only the index identifies the kanji, the slug may be percent-encoded or not depending on
+// where the link came from.
+#[derive(Debug, Clone)]
+pub struct KanjiId {
+    pub index: u32,
+    pub slug: String,
+}
+
+impl KanjiId {
+    pub fn parse(link: &str) -> Option<Self> {
+        let segment = link
+            .split(['?', '#'])
+            .next()?
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()?;
+        let (index, slug) = segment.split_once('-').unwrap_or((segment, ""));
+
+        Some(Self {
+            index: index.parse().ok()?,
+            slug: slug.to_string(),
+        })
+    }
+
+    pub fn path(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl PartialEq for KanjiId {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl Eq for KanjiId {}
+
+impl Hash for KanjiId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+    }
+}
+
+impl PartialOrd for KanjiId {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for KanjiId {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.index.cmp(&other.index)
+    }
+}
+
+impl fmt::Display for KanjiId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.slug.is_empty() {
+            write!(f, "/kanji/{}", self.index)
+        } else {
+            write!(f, "/kanji/{}-{}", self.index, self.slug)
+        }
+    }
+}
+
+impl Serialize for KanjiId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for KanjiId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let link = String::deserialize(deserializer)?;
+        KanjiId::parse(&link)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid kanji link: {}", link)))
+    }
+}
+
+// Links the scraper couldn't find were stored as empty strings, read those as missing
+fn optional_kanji_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<KanjiId>, D::Error> {
+    Ok(Option::<String>::deserialize(deserializer)?
+        .as_deref()
+        .and_then(KanjiId::parse))
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct KanjiListing {
     pub index: i32,
     pub kanji: String,
     pub meaning: String,
     pub is_radical: bool,
-    pub link: String,
+    pub link: KanjiId,
     pub has_image: bool,
     pub practice: bool,
 }
 
+// Kanji without a unicode character are stored as the path of their image
+fn is_link(value: &str) -> bool {
+    value.starts_with('/') || value.contains("://")
+}
+
 impl KanjiListing {
+    // Apply `f` to the image source of image kanji; page links are KanjiIds
+    pub fn map_links(&mut self, f: &mut impl FnMut(&mut String)) {
+        if self.has_image {
+            f(&mut self.kanji);
+        }
+    }
+
     // Search by exact index
     pub fn search_by_index<'a>(kanjis: &'a [KanjiListing], target_index: i32) -> Option<&'a KanjiListing> {
         kanjis.iter().find(|k| k.index == target_index)
@@ -84,7 +189,7 @@ impl KanjiListing {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct KanjiDetail {
     pub index: u32,
-    pub link: String,
+    pub link: KanjiId,
     pub kanji: String,
     pub meanings: Vec<String>,
     pub tags: Vec<Tag>,
@@ -96,8 +201,10 @@ pub struct KanjiDetail {
     pub usefulness: u8,
     pub used_in: Vec<UsedIn>,
     pub synonyms: Vec<SynonymEntry>,
-    pub prev_link: Option<String>,
-    pub next_link: Option<String>,
+    #[serde(default, deserialize_with = "optional_kanji_id")]
+    pub prev_link: Option<KanjiId>,
+    #[serde(default, deserialize_with = "optional_kanji_id")]
+    pub next_link: Option<KanjiId>,
     pub breakdown: String,
     pub lookalikes: Vec<Lookalike>,
     pub practice: bool,
@@ -105,6 +212,40 @@ pub struct KanjiDetail {
 
 impl KanjiDetail
 {
+    // Apply `f` to every tag link and image source stored on the page; page links are KanjiIds
+    pub fn map_links(&mut self, f: &mut impl FnMut(&mut String)) {
+        if is_link(&self.kanji) {
+            f(&mut self.kanji);
+        }
+
+        for tag in &mut self.tags {
+            f(&mut tag.link);
+        }
+
+        for jukugo in &mut self.jukugo {
+            jukugo.map_links(f);
+        }
+
+        for used_in in &mut self.used_in {
+            if is_link(&used_in.kanji) {
+                f(&mut used_in.kanji);
+            }
+        }
+
+        for lookalike in &mut self.lookalikes {
+            if is_link(&lookalike.kanji) {
+                f(&mut lookalike.kanji);
+            }
+            if is_link(&lookalike.radical) {
+                f(&mut lookalike.radical);
+            }
+        }
+    }
+
+    pub fn has_tag(&self, name: &str) -> bool {
+        self.tags.iter().any(|tag| tag.is_named(name))
+    }
+
     pub fn update_kanji_list(input_path: &str, output_path: &str) -> Result<(), Box<dyn std::error::Error>> {
         use std::fs;
         // Read the JSON file
@@ -144,6 +285,23 @@ pub struct Jukugo  {
     pub components: Vec<Component>,
 }
 
+impl Jukugo {
+    pub fn map_links(&mut self, f: &mut impl FnMut(&mut String)) {
+        for tag in &mut self.tags {
+            f(&mut tag.link);
+        }
+        for component in &mut self.components {
+            if let Some(image_src) = &mut component.image_src {
+                f(image_src);
+            }
+        }
+    }
+
+    pub fn has_tag(&self, name: &str) -> bool {
+        self.tags.iter().any(|tag| tag.is_named(name))
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SynonymEntry {
     pub japanese: String,
@@ -154,7 +312,8 @@ pub struct SynonymEntry {
 pub struct Component {
     pub kanji: String,
     pub meaning: String,
-    pub href: String,
+    #[serde(default, deserialize_with = "optional_kanji_id")]
+    pub href: Option<KanjiId>,
     pub image_src: Option<String>
 }
 
@@ -165,21 +324,35 @@ pub struct Tag
     pub link: String,
 }
 
+impl Tag {
+    // Tag names are abbreviations like "PN" or "KUNKUN", compared ignoring case and spacing
+    pub fn normalize_name(name: &str) -> String {
+        name.split_whitespace().collect::<String>().to_uppercase()
+    }
+
+    pub fn is_named(&self, name: &str) -> bool {
+        Tag::normalize_name(&self.name) == Tag::normalize_name(name)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UsedIn
 {
     pub kanji: String,
-    pub link: String,
+    #[serde(default, deserialize_with = "optional_kanji_id")]
+    pub link: Option<KanjiId>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Lookalike {
     pub kanji: String,
-    pub kanji_link: String,
+    #[serde(default, deserialize_with = "optional_kanji_id")]
+    pub kanji_link: Option<KanjiId>,
     pub meaning: String,
     pub hint: String,
     pub radical: String,
-    pub radical_link: String,
+    #[serde(default, deserialize_with = "optional_kanji_id")]
+    pub radical_link: Option<KanjiId>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -190,15 +363,36 @@ pub struct PracticeItem {
     pub meaning: String,
 }
 
-#[derive(Debug)]
-pub enum PracticeType {
-    Meaning,
-    Onyomi,
-    Kunyomi,
+// What we saw the last time a detail page was fetched, used to skip unchanged pages
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PageMeta {
+    pub content_hash: String,
+    pub fetched_at: u64,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: Value,
+    pub new: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChangelogEntry {
+    pub kanji_id: KanjiId,
+    pub kanji: String,
+    pub changed_at: u64,
+    pub changes: Vec<FieldChange>,
 }
 
 pub struct KanjiDatabase {
     pub kanjis: Vec<KanjiListing>,
+    // Cached detail pages
+    pub details: BTreeMap<KanjiId, KanjiDetail>,
+    pub pages: BTreeMap<KanjiId, PageMeta>,
+    pub changelog: Vec<ChangelogEntry>,
 }
 
 impl KanjiDatabase {
@@ -211,12 +405,152 @@ impl KanjiDatabase {
     ) -> Vec<&KanjiListing> {
         KanjiListing::search(&self.kanjis, index, kanji, meaning)
     }
+
+    pub fn detail(&self, kanji_id: &KanjiId) -> Option<&KanjiDetail> {
+        self.details.get(kanji_id)
+    }
+
+    // Details are keyed by index first, so this is a range lookup rather than a scan
+    pub fn detail_by_index(&self, index: u32) -> Option<&KanjiDetail> {
+        let start = KanjiId { index, slug: String::new() };
+        self.details
+            .range(start..)
+            .next()
+            .filter(|(kanji_id, _)| kanji_id.index == index)
+            .map(|(_, detail)| detail)
+    }
+
+    pub fn is_practiced(&self, index: u32) -> bool {
+        KanjiListing::search_by_index(&self.kanjis, index as i32)
+            .is_some_and(|k| k.practice)
+    }
+
+    // Store a freshly scraped page, keeping the practice flag owned by the listing
+    pub fn insert_detail(&mut self, kanji_id: &KanjiId, mut detail: KanjiDetail) -> KanjiDetail {
+        detail.practice = self.is_practiced(detail.index);
+        self.details.insert(kanji_id.clone(), detail.clone());
+        detail
+    }
+
+    // Update the practice flag on the listing and on the cached detail page, if any
+    pub fn set_practice(&mut self, index: u32, practice: bool) -> Result<(), String> {
+        let listing = self.kanjis
+            .iter_mut()
+            .find(|k| k.index == index as i32)
+            .ok_or_else(|| format!("Kanji with index {} not found", index))?;
+        listing.practice = practice;
+
+        // KanjiIds compare by index alone, so the slug isn't needed for the lookup
+        if let Some(detail) = self.details.get_mut(&KanjiId { index, slug: String::new() }) {
+            detail.practice = practice;
+        }
+
+        Ok(())
+    }
+
+    pub fn practice_pool(&self) -> Vec<KanjiDetail> {
+        self.details
+            .values()
+            .filter(|detail| detail.practice)
+            .cloned()
+            .collect()
+    }
 }
 
-pub struct KanjiDatabaseState(pub Mutex<KanjiDatabase>);
+pub struct KanjiDatabaseState {
+    db: Arc<RwLock<KanjiDatabase>>,
+    flusher: Flusher,
+}
 
 impl KanjiDatabaseState {
     pub fn new(db: KanjiDatabase) -> Self {
-        Self(Mutex::new(db))
+        let db = Arc::new(RwLock::new(db));
+        let flusher = Flusher::spawn(db.clone());
+        Self { db, flusher }
+    }
+
+    pub fn read(&self) -> Result<RwLockReadGuard<'_, KanjiDatabase>, String> {
+        self.db.read().map_err(|_| "Failed to lock database".to_string())
+    }
+
+    pub fn write(&self) -> Result<RwLockWriteGuard<'_, KanjiDatabase>, String> {
+        self.db.write().map_err(|_| "Failed to lock database".to_string())
+    }
+
+    // Schedule the given files to be written by the background flusher
+    pub fn mark_dirty(&self, targets: &[FlushTarget]) {
+        for target in targets {
+            self.flusher.mark_dirty(*target);
+        }
+    }
+
+    // Write pending changes right away instead of waiting for the background flusher
+    pub fn flush_now(&self) -> Result<(), String> {
+        self.flusher.flush_now()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATH: &str = "/kanji/1-one-line-radical-%E4%B8%80";
+
+    fn parsed(link: &str) -> (u32, String) {
+        let kanji_id = KanjiId::parse(link).unwrap();
+        (kanji_id.index, kanji_id.slug)
+    }
+
+    #[test]
+    fn parses_absolute_and_relative_links() {
+        let expected = (1, "one-line-radical-%E4%B8%80".to_string());
+        assert_eq!(parsed(&format!("https://www.kanjidamage.com{}", PATH)), expected);
+        assert_eq!(parsed(&format!("http://localhost:8000{}", PATH)), expected);
+        assert_eq!(parsed(PATH), expected);
+    }
+
+    #[test]
+    fn ignores_trailing_slash_query_and_fragment() {
+        let expected = (1, "one-line-radical-%E4%B8%80".to_string());
+        assert_eq!(parsed(&format!("{}/", PATH)), expected);
+        assert_eq!(parsed(&format!("https://www.kanjidamage.com{}/?ref=list#jukugo", PATH)), expected);
+    }
+
+    #[test]
+    fn parses_legacy_cache_keys() {
+        assert_eq!(parsed("1-one-line-radical-%E4%B8%80"), (1, "one-line-radical-%E4%B8%80".to_string()));
+        assert_eq!(parsed("252-dizzy"), (252, "dizzy".to_string()));
+        assert_eq!(parsed("/kanji/7"), (7, String::new()));
+    }
+
+    #[test]
+    fn rejects_links_without_an_index() {
+        assert!(KanjiId::parse("").is_none());
+        assert!(KanjiId::parse("/kanji/").is_none());
+        assert!(KanjiId::parse("https://www.kanjidamage.com/kanji/one-line-radical").is_none());
+    }
+
+    #[test]
+    fn identity_is_the_index() {
+        let encoded = KanjiId::parse(PATH).unwrap();
+        let decoded = KanjiId::parse("/kanji/1-one-line-radical-一").unwrap();
+        assert_eq!(encoded, decoded);
+        assert_ne!(encoded, KanjiId::parse("/kanji/2-two-%E4%BA%8C").unwrap());
+    }
+
+    #[test]
+    fn tag_names_ignore_case_and_spacing() {
+        let tag = Tag { name: "KUN KUN".to_string(), link: "/tags/kunkun".to_string() };
+        assert!(tag.is_named("kunkun"));
+        assert!(tag.is_named(" Kun  Kun "));
+        assert!(!tag.is_named("KUN"));
+    }
+
+    #[test]
+    fn serializes_as_site_path() {
+        let kanji_id = KanjiId::parse(&format!("https://www.kanjidamage.com{}/", PATH)).unwrap();
+        assert_eq!(serde_json::to_value(&kanji_id).unwrap(), PATH);
+        assert_eq!(serde_json::from_value::<KanjiId>(PATH.into()).unwrap().to_string(), PATH);
+        assert!(serde_json::from_value::<KanjiId>("kanji".into()).is_err());
     }
 }
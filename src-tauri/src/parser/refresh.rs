@@ -0,0 +1,157 @@
+use reqwest::header::{HeaderMap, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use super::config::ScraperConfig;
+use super::flusher::FlushTarget;
+use super::http::{check_status, HttpClient};
+use super::models::{ChangelogEntry, FieldChange, KanjiDatabaseState, KanjiDetail, KanjiId, PageMeta};
+use super::parse_kanji_page;
+use crate::storage;
+
+enum FetchOutcome {
+    NotModified,
+    Fetched {
+        html: String,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+}
+
+async fn fetch_page(client: &HttpClient, url: &str, previous: Option<&PageMeta>) -> Result<FetchOutcome, String> {
+    let mut headers = HeaderMap::new();
+
+    if let Some(meta) = previous {
+        // Skip validators that aren't valid header values rather than failing the request
+        if let Some(etag) = meta.etag.as_deref().and_then(|v| HeaderValue::from_str(v).ok()) {
+            headers.insert(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = meta.last_modified.as_deref().and_then(|v| HeaderValue::from_str(v).ok()) {
+            headers.insert(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = client.get(url, headers).await?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(FetchOutcome::NotModified);
+    }
+    let response = check_status(url, response)?;
+
+    let header = |name| {
+        response.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from)
+    };
+    let etag = header(ETAG);
+    let last_modified = header(LAST_MODIFIED);
+
+    let html = response.text().await.map_err(|e| e.to_string())?;
+
+    Ok(FetchOutcome::Fetched { html, etag, last_modified })
+}
+
+pub fn content_hash(html: &str) -> String {
+    Sha256::digest(html.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// Compare two versions of a page field by field, ignoring the user's practice flag
+pub fn diff_details(old: &KanjiDetail, new: &KanjiDetail) -> Vec<FieldChange> {
+    let (Ok(Value::Object(old)), Ok(Value::Object(new))) = (serde_json::to_value(old), serde_json::to_value(new)) else {
+        return Vec::new();
+    };
+
+    new.iter()
+        .filter(|(field, _)| field.as_str() != "practice")
+        .filter_map(|(field, new_value)| {
+            let old_value = old.get(field).cloned().unwrap_or(Value::Null);
+            (old_value != *new_value).then(|| FieldChange {
+                field: field.clone(),
+                old: old_value,
+                new: new_value.clone(),
+            })
+        })
+        .collect()
+}
+
+// Fetch a detail page and store it. Pages the server reports as not modified, or whose
+// content hash is unchanged, keep the cached detail; otherwise the page is re-parsed and
+// any fields the site changed are recorded in the changelog.
+pub async fn refresh_page(
+    state: &KanjiDatabaseState,
+    client: &HttpClient,
+    config: &ScraperConfig,
+    kanji_id: &KanjiId
+) -> Result<KanjiDetail, String> {
+    let url = config.resolve(&kanji_id.path());
+
+    let (previous_meta, previous_detail) = {
+        let db = state.read()?;
+        (db.pages.get(kanji_id).cloned(), db.detail(kanji_id).cloned())
+    };
+
+    // Only send conditional headers when there's a cached page to fall back on
+    let conditional = previous_detail.as_ref().and(previous_meta.as_ref());
+    let outcome = fetch_page(client, &url, conditional).await?;
+    let fetched_at = storage::unix_now();
+
+    let (html, etag, last_modified) = match outcome {
+        FetchOutcome::Fetched { html, etag, last_modified } => (html, etag, last_modified),
+        FetchOutcome::NotModified => {
+            let detail = previous_detail
+                .ok_or_else(|| format!("{} was reported unchanged but isn't cached", url))?;
+
+            if let Some(meta) = state.write()?.pages.get_mut(kanji_id) {
+                meta.fetched_at = fetched_at;
+            }
+            state.mark_dirty(&[FlushTarget::Pages]);
+            return Ok(detail);
+        }
+    };
+
+    let meta = PageMeta {
+        content_hash: content_hash(&html),
+        fetched_at,
+        etag,
+        last_modified,
+    };
+
+    if let (Some(previous), Some(detail)) = (&previous_meta, &previous_detail) {
+        if previous.content_hash == meta.content_hash {
+            state.write()?.pages.insert(kanji_id.clone(), meta);
+            state.mark_dirty(&[FlushTarget::Pages]);
+            return Ok(detail.clone());
+        }
+    }
+
+    let detail = parse_kanji_page(kanji_id, &html);
+    let changes = previous_detail
+        .map(|old| diff_details(&old, &detail))
+        .unwrap_or_default();
+
+    let mut targets = vec![FlushTarget::Details, FlushTarget::Pages];
+    let detail = {
+        let mut db = state.write()?;
+        let detail = db.insert_detail(kanji_id, detail);
+        db.pages.insert(kanji_id.clone(), meta);
+
+        if !changes.is_empty() {
+            db.changelog.push(ChangelogEntry {
+                kanji_id: kanji_id.clone(),
+                kanji: detail.kanji.clone(),
+                changed_at: fetched_at,
+                changes,
+            });
+            targets.push(FlushTarget::Changelog);
+        }
+
+        detail
+    };
+
+    state.mark_dirty(&targets);
+    Ok(detail)
+}
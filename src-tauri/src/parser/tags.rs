@@ -0,0 +1,135 @@
+use std::collections::{BTreeMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use super::config::ScraperConfigState;
+use super::models::{Jukugo, KanjiDatabase, KanjiDatabaseState, KanjiId, KanjiListing, Tag};
+use crate::text::strip_reading_suffix;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TagSummary {
+    pub name: String,
+    pub link: String,
+    pub kanji_count: usize,
+    // A word listed under several kanji counts once
+    pub jukugo_count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TaggedJukugo {
+    // The page the word was found on
+    pub kanji_id: KanjiId,
+    pub kanji: String,
+    pub jukugo: Jukugo,
+}
+
+struct TagCounts<'a> {
+    tag: &'a Tag,
+    kanji: HashSet<u32>,
+    words: HashSet<&'a str>,
+}
+
+// Tags are grouped by name, since that's what they're looked up by
+fn counts<'a, 'm>(index: &'m mut BTreeMap<String, TagCounts<'a>>, tag: &'a Tag) -> &'m mut TagCounts<'a> {
+    index.entry(Tag::normalize_name(&tag.name)).or_insert_with(|| TagCounts {
+        tag,
+        kanji: HashSet::new(),
+        words: HashSet::new(),
+    })
+}
+
+// Every kanji and jukugo tag on the cached pages, sorted by name
+pub fn tag_index(db: &KanjiDatabase) -> Vec<TagSummary> {
+    let mut index = BTreeMap::new();
+
+    for detail in db.details.values() {
+        for tag in &detail.tags {
+            counts(&mut index, tag).kanji.insert(detail.index);
+        }
+        for jukugo in &detail.jukugo {
+            for tag in &jukugo.tags {
+                counts(&mut index, tag).words.insert(strip_reading_suffix(&jukugo.japanese));
+            }
+        }
+    }
+
+    index
+        .into_values()
+        .map(|counts| TagSummary {
+            name: counts.tag.name.trim().to_string(),
+            link: counts.tag.link.clone(),
+            kanji_count: counts.kanji.len(),
+            jukugo_count: counts.words.len(),
+        })
+        .collect()
+}
+
+#[tauri::command]
+pub fn get_tag_index(
+    state: State<'_, KanjiDatabaseState>,
+    scraper_config: State<'_, ScraperConfigState>
+) -> Result<Vec<TagSummary>, String> {
+    let db = state.read()?;
+    let config = scraper_config.read()?;
+
+    Ok(tag_index(&db)
+        .into_iter()
+        .map(|summary| TagSummary {
+            link: config.resolve(&summary.link),
+            ..summary
+        })
+        .collect())
+}
+
+// Cached kanji carrying the tag, in KanjiDamage order
+#[tauri::command]
+pub fn kanji_by_tag(
+    state: State<'_, KanjiDatabaseState>,
+    scraper_config: State<'_, ScraperConfigState>,
+    name: String
+) -> Result<Vec<KanjiListing>, String> {
+    let db = state.read()?;
+    let config = scraper_config.read()?;
+
+    let mut kanjis: Vec<KanjiListing> = db.kanjis
+        .iter()
+        .filter(|k| db.detail_by_index(k.index as u32).is_some_and(|d| d.has_tag(&name)))
+        .cloned()
+        .map(|k| config.resolve_listing(k))
+        .collect();
+
+    kanjis.sort_by_key(|k| k.index);
+    Ok(kanjis)
+}
+
+// Words carrying the tag, each listed once under the first kanji it was found on
+#[tauri::command]
+pub fn jukugo_by_tag(
+    state: State<'_, KanjiDatabaseState>,
+    scraper_config: State<'_, ScraperConfigState>,
+    name: String
+) -> Result<Vec<TaggedJukugo>, String> {
+    let db = state.read()?;
+    let config = scraper_config.read()?;
+
+    let mut seen_words = HashSet::new();
+    let mut tagged = Vec::new();
+    for detail in db.details.values() {
+        for jukugo in &detail.jukugo {
+            if !jukugo.has_tag(&name) || !seen_words.insert(strip_reading_suffix(&jukugo.japanese)) {
+                continue;
+            }
+
+            let mut jukugo = jukugo.clone();
+            jukugo.map_links(&mut |link: &mut String| *link = config.resolve(link));
+            tagged.push(TaggedJukugo {
+                kanji_id: detail.link.clone(),
+                kanji: config.resolve(&detail.kanji),
+                jukugo,
+            });
+        }
+    }
+
+    Ok(tagged)
+}
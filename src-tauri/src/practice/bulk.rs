@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::dictionary::kanjidic::KanjidicState;
+use crate::parser::filter::KanjiFilter;
+use crate::parser::flusher::FlushTarget;
+use crate::parser::models::KanjiDatabaseState;
+use crate::user_data::history::{Change, HistoryState};
+use crate::user_data::lists::StudyListsState;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BulkUpdateSummary {
+    pub matched: usize,
+    // Matches whose flag actually flipped
+    pub changed: usize,
+    pub practice: bool,
+}
+
+// Add every kanji matching `filter` to the practice pool, or take them out of it
+#[tauri::command]
+pub fn bulk_update_practice(
+    state: State<'_, KanjiDatabaseState>,
+    kanjidic: State<'_, KanjidicState>,
+    lists: State<'_, StudyListsState>,
+    history: State<'_, HistoryState>,
+    filter: KanjiFilter,
+    practice: bool
+) -> Result<BulkUpdateSummary, String> {
+    // Most likely a filter field that didn't make it across, not a request to change every kanji
+    if filter.is_empty() {
+        return Err("Pick at least one filter before changing kanji in bulk".to_string());
+    }
+
+    let (matched, indexes) = {
+        let mut db = state.write()?;
+        let metadata = kanjidic.read()?;
+        let lists = lists.read()?;
+
+        let matched: Vec<(u32, bool)> = filter
+            .apply(&db, &metadata, &lists)
+            .into_iter()
+            .map(|listing| (listing.index as u32, listing.practice))
+            .collect();
+
+        // One write lock for the whole batch, so nothing sees it half applied
+        let mut indexes = Vec::new();
+        for (index, current) in &matched {
+            if *current != practice {
+                db.set_practice(*index, practice)?;
+                indexes.push(*index);
+            }
+        }
+
+        (matched.len(), indexes)
+    };
+
+    let changed = indexes.len();
+    if changed > 0 {
+        state.mark_dirty(&[FlushTarget::Listings, FlushTarget::Details]);
+        let description = if practice {
+            format!("Add {} kanji to practice", changed)
+        } else {
+            format!("Remove {} kanji from practice", changed)
+        };
+        history.record(description, Change::Practice { indexes, practice })?;
+    }
+
+    Ok(BulkUpdateSummary { matched, changed, practice })
+}
@@ -0,0 +1,294 @@
+use std::collections::{HashMap, HashSet};
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::grading::{normalize_romaji, normalizer};
+use super::models::{Choice, JukugoVariant, PracticeCard, PracticeType};
+use super::onyomi_readings;
+use crate::parser::models::{KanjiDatabase, KanjiDetail, KanjiId};
+
+// Options per question, counting the right one
+pub const DEFAULT_CHOICE_COUNT: usize = 4;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ChoiceOptions {
+    // Defaults to DEFAULT_CHOICE_COUNT
+    pub choice_count: Option<usize>,
+    // Same seed, same cards, options and order
+    pub seed: Option<u64>,
+}
+
+// How many random kanji to look at when the confusable ones run out
+const FALLBACK_SAMPLE: usize = 32;
+
+// Which cached kanji are easy to confuse with which, built once per batch of cards
+pub struct DistractorIndex<'a> {
+    details: Vec<&'a KanjiDetail>,
+    by_id: HashMap<&'a KanjiId, &'a KanjiDetail>,
+    // Kanji -> the components it's built from, recovered from each component's "used in" list
+    components: HashMap<&'a KanjiId, Vec<&'a KanjiId>>,
+    by_onyomi: HashMap<String, Vec<&'a KanjiId>>,
+}
+
+impl<'a> DistractorIndex<'a> {
+    pub fn new(db: &'a KanjiDatabase) -> Self {
+        let details: Vec<&KanjiDetail> = db.details.values().collect();
+        let mut by_id = HashMap::new();
+        let mut components: HashMap<&KanjiId, Vec<&KanjiId>> = HashMap::new();
+        let mut by_onyomi: HashMap<String, Vec<&KanjiId>> = HashMap::new();
+
+        for detail in &details {
+            by_id.insert(&detail.link, *detail);
+
+            for used_in in &detail.used_in {
+                if let Some(link) = &used_in.link {
+                    components.entry(link).or_default().push(&detail.link);
+                }
+            }
+
+            for reading in onyomi_readings(detail) {
+                by_onyomi.entry(normalize_romaji(&reading)).or_default().push(&detail.link);
+            }
+        }
+
+        Self { details, by_id, components, by_onyomi }
+    }
+
+    // Confusable kanji, most confusable group first: lookalikes, then kanji sharing a
+    // component, then kanji sharing an onyomi. Each group is shuffled with `rng`.
+    fn candidates(&self, detail: &KanjiDetail, rng: &mut impl Rng) -> Vec<&'a KanjiDetail> {
+        let lookalikes: Vec<&KanjiId> = detail.lookalikes
+            .iter()
+            .filter_map(|l| l.kanji_link.as_ref())
+            .collect();
+
+        // Kanji it's part of, kanji it's made of, and kanji made of the same parts
+        let own_components = self.components.get(&detail.link).cloned().unwrap_or_default();
+        let mut shared_components: Vec<&KanjiId> = detail.used_in
+            .iter()
+            .filter_map(|u| u.link.as_ref())
+            .collect();
+        shared_components.extend(own_components.iter().copied());
+        for component in &own_components {
+            if let Some(parent) = self.by_id.get(component) {
+                shared_components.extend(parent.used_in.iter().filter_map(|u| u.link.as_ref()));
+            }
+        }
+
+        let shared_onyomi: Vec<&KanjiId> = onyomi_readings(detail)
+            .iter()
+            .filter_map(|reading| self.by_onyomi.get(&normalize_romaji(reading)))
+            .flatten()
+            .copied()
+            .collect();
+
+        let mut seen = HashSet::from([&detail.link]);
+        let mut candidates = Vec::new();
+
+        for mut group in [lookalikes, shared_components, shared_onyomi] {
+            group.shuffle(rng);
+            for kanji_id in group {
+                if seen.insert(kanji_id) {
+                    if let Some(candidate) = self.by_id.get(kanji_id) {
+                        candidates.push(*candidate);
+                    }
+                }
+            }
+        }
+
+        // Small caches may not have enough related kanji, pad with random ones
+        for candidate in self.details.choose_multiple(rng, FALLBACK_SAMPLE) {
+            if seen.insert(&candidate.link) {
+                candidates.push(*candidate);
+            }
+        }
+
+        candidates
+    }
+}
+
+// Texts a kanji could contribute as a wrong option for this kind of question
+fn option_texts(detail: &KanjiDetail, practice_type: PracticeType) -> Vec<String> {
+    match practice_type {
+        PracticeType::Meaning => vec![detail.meanings.join(", ")],
+        PracticeType::Onyomi => onyomi_readings(detail),
+        PracticeType::Kunyomi => detail.kunyomi.iter().map(|k| k.reading.clone()).collect(),
+        PracticeType::Jukugo(JukugoVariant::Reading) => detail.jukugo.iter().map(|j| j.reading.clone()).collect(),
+        PracticeType::Jukugo(JukugoVariant::Meaning) => detail.jukugo.iter().map(|j| j.english.clone()).collect(),
+        PracticeType::Lookalike | PracticeType::Production(_) => vec![detail.kanji.clone()],
+        // Not on the page, see stroke_count_choices
+        PracticeType::StrokeCount => Vec::new(),
+    }
+}
+
+// Turn a typed-answer card into a multiple-choice one. The right option is kept in
+// `answers` so grading works the same either way.
+pub fn add_choices(card: &mut PracticeCard, index: &DistractorIndex, choice_count: usize, rng: &mut impl Rng) {
+    // Lookalike cards come with their options already
+    if !card.choices.is_empty() || card.answers.is_empty() {
+        return;
+    }
+    if card.practice_type == PracticeType::StrokeCount {
+        stroke_count_choices(card, choice_count, rng);
+        return;
+    }
+
+    let Some(detail) = index.by_id.get(&card.kanji_id) else {
+        return;
+    };
+
+    let normalize = normalizer(card.practice_type);
+
+    // Meanings are offered as a whole, e.g. "one, single"
+    let correct = match card.practice_type {
+        PracticeType::Meaning | PracticeType::Jukugo(JukugoVariant::Meaning) => card.answers.join(", "),
+        _ => card.answers[0].clone(),
+    };
+    if !card.answers.contains(&correct) {
+        card.answers.push(correct.clone());
+    }
+
+    // Never offer something that would be graded correct, or the same option twice
+    let mut taken: HashSet<String> = card.answers.iter().map(|a| normalize(a)).collect();
+
+    let mut choices = vec![Choice {
+        text: correct,
+        kanji_id: Some(card.kanji_id.clone()),
+    }];
+
+    for candidate in index.candidates(detail, rng) {
+        if choices.len() >= choice_count {
+            break;
+        }
+
+        let mut texts = option_texts(candidate, card.practice_type);
+        texts.shuffle(rng);
+
+        if let Some(text) = texts.into_iter().find(|t| {
+            let normalized = normalize(t);
+            !normalized.is_empty() && !taken.contains(&normalized)
+        }) {
+            taken.insert(normalize(&text));
+            choices.push(Choice {
+                text,
+                kanji_id: Some(candidate.link.clone()),
+            });
+        }
+    }
+
+    choices.shuffle(rng);
+    card.choices = choices;
+}
+
+// Miscounts are off by one or two, so offer the counts next to the right one
+fn stroke_count_choices(card: &mut PracticeCard, choice_count: usize, rng: &mut impl Rng) {
+    let Some(count) = card.answers.first().and_then(|a| a.parse::<usize>().ok()) else {
+        return;
+    };
+
+    let mut nearby: Vec<usize> = (count.saturating_sub(3)..=count + 3)
+        .filter(|n| *n > 0 && *n != count)
+        .collect();
+    nearby.sort_by_key(|n| n.abs_diff(count));
+
+    let mut choices: Vec<Choice> = std::iter::once(count)
+        .chain(nearby.into_iter().take(choice_count.saturating_sub(1)))
+        .map(|n| Choice {
+            text: n.to_string(),
+            kanji_id: None,
+        })
+        .collect();
+    choices.shuffle(rng);
+    card.choices = choices;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::practice::models::CardFilter;
+    use crate::practice::{build_cards, order_by_usefulness};
+    use crate::practice::scheduler::ReviewQueue;
+
+    fn detail(index: u32, kanji: &str, meaning: &str, onyomi: &str) -> KanjiDetail {
+        KanjiDetail {
+            index,
+            link: KanjiId::parse(&format!("/kanji/{}-{}", index, meaning)).unwrap(),
+            kanji: kanji.to_string(),
+            meanings: vec![meaning.to_string()],
+            tags: Vec::new(),
+            description: None,
+            onyomi: vec![(onyomi.to_string(), String::new())],
+            kunyomi: Vec::new(),
+            jukugo: Vec::new(),
+            mnemonic: None,
+            usefulness: (index % 5) as u8,
+            used_in: Vec::new(),
+            synonyms: Vec::new(),
+            prev_link: None,
+            next_link: None,
+            breakdown: String::new(),
+            lookalikes: Vec::new(),
+            practice: true,
+        }
+    }
+
+    fn database() -> KanjiDatabase {
+        let details = [
+            detail(1, "一", "one", "ICHI"),
+            detail(2, "二", "two", "NI"),
+            detail(3, "三", "three", "SAN"),
+            detail(4, "四", "four", "SHI"),
+            detail(5, "五", "five", "GO"),
+            detail(6, "六", "six", "ROKU"),
+            detail(7, "七", "seven", "SHICHI"),
+            detail(8, "八", "eight", "HACHI"),
+        ];
+
+        KanjiDatabase {
+            kanjis: Vec::new(),
+            details: details.into_iter().map(|d| (d.link.clone(), d)).collect(),
+            pages: BTreeMap::new(),
+            changelog: Vec::new(),
+        }
+    }
+
+    // Option texts of every card, in card order
+    fn replay(db: &KanjiDatabase, seed: u64) -> Vec<Vec<String>> {
+        let filter = CardFilter {
+            weight_by_usefulness: true,
+            ..CardFilter::default()
+        };
+        let mut rng = StdRng::seed_from_u64(seed);
+        let index = DistractorIndex::new(db);
+
+        let mut cards = build_cards(db.details.values(), PracticeType::Meaning, &filter);
+        for card in &mut cards {
+            add_choices(card, &index, DEFAULT_CHOICE_COUNT, &mut rng);
+        }
+        order_by_usefulness(&mut cards, |_| ReviewQueue::New, |card| card.usefulness, &filter, &mut rng);
+
+        cards
+            .iter()
+            .map(|card| card.choices.iter().map(|c| c.text.clone()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_gives_same_choices_and_order() {
+        let db = database();
+        let first = replay(&db, 42);
+
+        assert_eq!(first.len(), 8);
+        assert!(first.iter().all(|choices| choices.len() == DEFAULT_CHOICE_COUNT));
+        assert_eq!(first, replay(&db, 42));
+        assert_ne!(first, replay(&db, 43));
+    }
+}
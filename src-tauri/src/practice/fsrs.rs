@@ -0,0 +1,380 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::models::{Rating, ReviewEntry};
+use crate::parser::models::KanjiId;
+use crate::storage::SECONDS_PER_DAY;
+
+// FSRS-5, see https://github.com/open-spaced-repetition/fsrs4anki/wiki/The-Algorithm
+pub const DEFAULT_WEIGHTS: [f64; 19] = [
+    0.40255, 1.18385, 3.173, 15.69105, 7.1949, 0.5345, 1.4604, 0.0046, 1.54575, 0.1192,
+    1.01925, 1.9395, 0.11, 0.29605, 2.2698, 0.2315, 2.9898, 0.51655, 0.6621,
+];
+
+// Range each weight is kept in while optimizing, from the reference optimizer
+const WEIGHT_BOUNDS: [(f64, f64); 19] = [
+    (0.001, 100.0), (0.001, 100.0), (0.001, 100.0), (0.001, 100.0),
+    (1.0, 10.0), (0.001, 4.0), (0.001, 4.0), (0.001, 0.75),
+    (0.0, 4.5), (0.0, 0.8), (0.001, 3.5), (0.001, 5.0),
+    (0.001, 0.25), (0.001, 0.9), (0.0, 4.0), (0.0, 1.0),
+    (1.0, 6.0), (0.0, 2.0), (0.0, 2.0),
+];
+
+const DECAY: f64 = -0.5;
+// Chosen so that retrievability is 90% after `stability` days
+const FACTOR: f64 = 19.0 / 81.0;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct FsrsParameters {
+    pub weights: Vec<f64>,
+    // Probability of recall to schedule the next review at
+    pub target_retention: f64,
+    pub maximum_interval_days: u64,
+}
+
+impl Default for FsrsParameters {
+    fn default() -> Self {
+        Self {
+            weights: DEFAULT_WEIGHTS.to_vec(),
+            target_retention: 0.9,
+            maximum_interval_days: 36500,
+        }
+    }
+}
+
+// What FSRS remembers about a card between reviews
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct MemoryState {
+    pub stability: f64,
+    pub difficulty: f64,
+}
+
+fn grade(rating: Rating) -> f64 {
+    match rating {
+        Rating::Again => 1.0,
+        Rating::Hard => 2.0,
+        Rating::Good => 3.0,
+        Rating::Easy => 4.0,
+    }
+}
+
+pub struct Fsrs<'a> {
+    w: &'a [f64],
+}
+
+impl<'a> Fsrs<'a> {
+    pub fn new(parameters: &'a FsrsParameters) -> Self {
+        Self::with_weights(&parameters.weights)
+    }
+
+    // Fall back to the defaults if a hand-edited config has the wrong number of weights
+    fn with_weights(weights: &'a [f64]) -> Self {
+        if weights.len() == DEFAULT_WEIGHTS.len() {
+            Self { w: weights }
+        } else {
+            Self { w: &DEFAULT_WEIGHTS }
+        }
+    }
+
+    pub fn retrievability(elapsed_days: f64, stability: f64) -> f64 {
+        (1.0 + FACTOR * elapsed_days / stability).powf(DECAY)
+    }
+
+    // Days until recall probability drops to `retention`
+    pub fn interval_days(stability: f64, retention: f64, maximum: u64) -> u64 {
+        let days = stability / FACTOR * (retention.powf(1.0 / DECAY) - 1.0);
+        (days.round() as u64).clamp(1, maximum.max(1))
+    }
+
+    fn initial_difficulty(&self, g: f64) -> f64 {
+        self.w[4] - (self.w[5] * (g - 1.0)).exp() + 1.0
+    }
+
+    // The memory state after reviewing with `rating`, `elapsed_days` after the previous review
+    pub fn next_state(&self, state: Option<MemoryState>, elapsed_days: f64, rating: Rating) -> MemoryState {
+        let w = self.w;
+        let g = grade(rating);
+
+        let Some(state) = state else {
+            return MemoryState {
+                stability: w[(g as usize) - 1].max(0.01),
+                difficulty: self.initial_difficulty(g).clamp(1.0, 10.0),
+            };
+        };
+        let (s, d) = (state.stability, state.difficulty);
+
+        // Difficulty moves against the grade, damped near the top and pulled back towards
+        // the difficulty of a first "Easy"
+        let delta = -w[6] * (g - 3.0);
+        let damped = d + delta * (10.0 - d) / 9.0;
+        let difficulty = (w[7] * self.initial_difficulty(4.0) + (1.0 - w[7]) * damped).clamp(1.0, 10.0);
+
+        let stability = if elapsed_days < 1.0 {
+            // Reviewed again the same day
+            s * (w[17] * (g - 3.0 + w[18])).exp()
+        } else {
+            let r = Self::retrievability(elapsed_days, s);
+            if rating == Rating::Again {
+                let forgotten = w[11] * d.powf(-w[12]) * ((s + 1.0).powf(w[13]) - 1.0) * (w[14] * (1.0 - r)).exp();
+                forgotten.min(s)
+            } else {
+                let hard_penalty = if rating == Rating::Hard { w[15] } else { 1.0 };
+                let easy_bonus = if rating == Rating::Easy { w[16] } else { 1.0 };
+                s * (w[8].exp() * (11.0 - d) * s.powf(-w[9]) * ((w[10] * (1.0 - r)).exp() - 1.0)
+                    * hard_penalty * easy_bonus + 1.0)
+            }
+        };
+
+        MemoryState {
+            stability: stability.clamp(0.01, 36500.0),
+            difficulty,
+        }
+    }
+}
+
+// Each kanji's reviews in order, as (answered_at, rating). Like the scheduler, passes within
+// a day of the last review that counted are left out.
+pub fn review_histories(entries: &[ReviewEntry]) -> BTreeMap<&KanjiId, Vec<(u64, Rating)>> {
+    let mut histories: BTreeMap<&KanjiId, Vec<(u64, Rating)>> = BTreeMap::new();
+    for entry in entries {
+        histories.entry(&entry.kanji_id).or_default().push((entry.answered_at, entry.rating()));
+    }
+
+    for history in histories.values_mut() {
+        history.sort_by_key(|(answered_at, _)| *answered_at);
+
+        let mut last_counted: Option<u64> = None;
+        history.retain(|&(answered_at, rating)| {
+            let same_day = last_counted.is_some_and(|last| answered_at.saturating_sub(last) < SECONDS_PER_DAY);
+            if rating != Rating::Again && same_day {
+                return false;
+            }
+            last_counted = Some(answered_at);
+            true
+        });
+    }
+    histories
+}
+
+// Replay a history and return the memory state after its last review
+pub fn replay(fsrs: &Fsrs, history: &[(u64, Rating)]) -> Option<MemoryState> {
+    let mut state = None;
+    let mut previous = None;
+
+    for &(answered_at, rating) in history {
+        let elapsed = previous.map_or(0.0, |p: u64| answered_at.saturating_sub(p) as f64 / SECONDS_PER_DAY as f64);
+        state = Some(fsrs.next_state(state, elapsed, rating));
+        previous = Some(answered_at);
+    }
+
+    state
+}
+
+// Mean log loss of the predicted recall probability over every review that came at least
+// a day after the previous one
+fn log_loss(weights: &[f64], histories: &[Vec<(u64, Rating)>]) -> (f64, usize) {
+    let fsrs = Fsrs::with_weights(weights);
+    let mut total = 0.0;
+    let mut count = 0;
+
+    for history in histories {
+        let mut state: Option<MemoryState> = None;
+        let mut previous: Option<u64> = None;
+
+        for &(answered_at, rating) in history {
+            let elapsed = previous.map_or(0.0, |p| answered_at.saturating_sub(p) as f64 / SECONDS_PER_DAY as f64);
+
+            if let (Some(memory), true) = (state, elapsed >= 1.0) {
+                let predicted = Fsrs::retrievability(elapsed, memory.stability).clamp(0.0001, 0.9999);
+                let recalled = rating != Rating::Again;
+                total -= if recalled { predicted.ln() } else { (1.0 - predicted).ln() };
+                count += 1;
+            }
+
+            state = Some(fsrs.next_state(state, elapsed, rating));
+            previous = Some(answered_at);
+        }
+    }
+
+    (if count > 0 { total / count as f64 } else { 0.0 }, count)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OptimizeResult {
+    pub weights: Vec<f64>,
+    pub reviews: usize,
+    pub loss_before: f64,
+    pub loss_after: f64,
+}
+
+// Too few spaced reviews and the fitted weights are mostly noise
+pub const MIN_OPTIMIZE_REVIEWS: usize = 50;
+
+const OPTIMIZE_ITERATIONS: usize = 200;
+const LEARNING_RATE: f64 = 0.05;
+const GRADIENT_STEP: f64 = 1e-4;
+
+// Fit the weights to the review history with projected gradient descent on the log loss,
+// starting from `initial`
+pub fn optimize(initial: &[f64], entries: &[ReviewEntry]) -> Result<OptimizeResult, String> {
+    let histories: Vec<_> = review_histories(entries).into_values().collect();
+    let mut weights = if initial.len() == DEFAULT_WEIGHTS.len() {
+        initial.to_vec()
+    } else {
+        DEFAULT_WEIGHTS.to_vec()
+    };
+
+    let (loss_before, reviews) = log_loss(&weights, &histories);
+    if reviews < MIN_OPTIMIZE_REVIEWS {
+        return Err(format!(
+            "Not enough review history to optimize: {} spaced reviews, need {}",
+            reviews, MIN_OPTIMIZE_REVIEWS
+        ));
+    }
+
+    let mut loss = loss_before;
+    for _ in 0..OPTIMIZE_ITERATIONS {
+        let mut gradient = vec![0.0; weights.len()];
+        for (i, slope) in gradient.iter_mut().enumerate() {
+            let mut nudged = weights.clone();
+            nudged[i] += GRADIENT_STEP;
+            *slope = (log_loss(&nudged, &histories).0 - loss) / GRADIENT_STEP;
+        }
+
+        let candidate: Vec<f64> = weights
+            .iter()
+            .zip(&gradient)
+            .zip(WEIGHT_BOUNDS)
+            .map(|((w, g), (low, high))| (w - LEARNING_RATE * g).clamp(low, high))
+            .collect();
+
+        let (candidate_loss, _) = log_loss(&candidate, &histories);
+        if candidate_loss >= loss {
+            break;
+        }
+        weights = candidate;
+        loss = candidate_loss;
+    }
+
+    Ok(OptimizeResult {
+        weights,
+        reviews,
+        loss_before,
+        loss_after: loss,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Expected values below were worked out separately from the published FSRS-5 formulas
+    // with DEFAULT_WEIGHTS
+    const REFERENCE_TOLERANCE: f64 = 1e-6;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < REFERENCE_TOLERANCE, "{} != {}", actual, expected);
+    }
+
+    fn review(kanji_id: &KanjiId, day: u64, rating: Rating) -> ReviewEntry {
+        ReviewEntry {
+            kanji_id: kanji_id.clone(),
+            practice_type: crate::practice::models::PracticeType::Meaning,
+            prompt: String::new(),
+            answer: String::new(),
+            correct: rating != Rating::Again,
+            answered_at: day * SECONDS_PER_DAY,
+            confused_with: None,
+            rating: Some(rating),
+        }
+    }
+
+    #[test]
+    fn first_review_uses_initial_stability_and_difficulty() {
+        let fsrs = Fsrs::with_weights(&DEFAULT_WEIGHTS);
+        let expected = [
+            (Rating::Again, 0.40255, 7.1949),
+            (Rating::Hard, 1.18385, 6.488305268471453),
+            (Rating::Good, 3.173, 5.282434422319005),
+            (Rating::Easy, 15.69105, 3.2245015893713678),
+        ];
+
+        for (rating, stability, difficulty) in expected {
+            let state = fsrs.next_state(None, 0.0, rating);
+            assert_close(state.stability, stability);
+            assert_close(state.difficulty, difficulty);
+        }
+    }
+
+    #[test]
+    fn later_reviews_match_reference_values() {
+        let fsrs = Fsrs::with_weights(&DEFAULT_WEIGHTS);
+        let first = fsrs.next_state(None, 0.0, Rating::Good);
+
+        assert_close(Fsrs::retrievability(3.0, first.stability), 0.9046982108893272);
+
+        let recalled = fsrs.next_state(Some(first), 3.0, Rating::Good);
+        assert_close(recalled.stability, 10.73892584613159);
+        assert_close(recalled.difficulty, 5.272967931287446);
+
+        let forgotten = fsrs.next_state(Some(recalled), 10.0, Rating::Again);
+        assert_close(forgotten.stability, 2.146380692406721);
+        assert_close(forgotten.difficulty, 6.790567694566929);
+
+        let same_day = fsrs.next_state(Some(first), 0.0, Rating::Good);
+        assert_close(same_day.stability, 4.466858064362218);
+    }
+
+    #[test]
+    fn interval_follows_target_retention() {
+        // At 90% retention the interval is the stability itself
+        assert_close(Fsrs::retrievability(10.738925846131586, 10.738925846131586), 0.9);
+        assert_eq!(Fsrs::interval_days(10.738925846131586, 0.9, 36500), 11);
+        assert_eq!(Fsrs::interval_days(10.738925846131586, 0.8, 36500), 26);
+        assert_eq!(Fsrs::interval_days(10.738925846131586, 0.8, 20), 20);
+        assert_eq!(Fsrs::interval_days(0.1, 0.9, 36500), 1);
+    }
+
+    #[test]
+    fn histories_skip_passes_on_the_same_day() {
+        let kanji_id = KanjiId::parse("/kanji/1-one").unwrap();
+        let mut entries = vec![
+            review(&kanji_id, 0, Rating::Good),
+            review(&kanji_id, 0, Rating::Good),
+            review(&kanji_id, 0, Rating::Again),
+            review(&kanji_id, 2, Rating::Good),
+        ];
+        entries[1].answered_at += 60;
+        entries[2].answered_at += 120;
+
+        let histories = review_histories(&entries);
+        let ratings: Vec<Rating> = histories[&kanji_id].iter().map(|(_, rating)| *rating).collect();
+        assert_eq!(ratings, [Rating::Good, Rating::Again, Rating::Good]);
+    }
+
+    #[test]
+    fn optimizer_needs_enough_reviews() {
+        let kanji_id = KanjiId::parse("/kanji/1-one").unwrap();
+        let entries: Vec<ReviewEntry> = (0..10).map(|i| review(&kanji_id, i * 2, Rating::Good)).collect();
+
+        assert!(optimize(&DEFAULT_WEIGHTS, &entries).is_err());
+    }
+
+    #[test]
+    fn optimizer_lowers_loss_within_bounds() {
+        // Everything is remembered long after the default weights expect it to be forgotten
+        let entries: Vec<ReviewEntry> = (1..=20)
+            .flat_map(|index| {
+                let kanji_id = KanjiId::parse(&format!("/kanji/{}-kanji", index)).unwrap();
+                [0, 5, 30, 120, 400].into_iter().map(move |day| review(&kanji_id, day, Rating::Good))
+            })
+            .collect();
+
+        let result = optimize(&DEFAULT_WEIGHTS, &entries).unwrap();
+        assert_eq!(result.reviews, 80);
+        assert!(result.loss_after < result.loss_before);
+        assert!(result.weights.iter().zip(WEIGHT_BOUNDS).all(|(w, (low, high))| (low..=high).contains(w)));
+    }
+}
@@ -0,0 +1,82 @@
+use super::models::{JukugoVariant, PracticeCard, PracticeType};
+use crate::text::{normalize_kana, strip_reading_suffix};
+use crate::user_data::answers::{KanjiAnswers, UserAnswer};
+
+pub fn normalize_romaji(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphabetic())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+// Lowercase and treat punctuation as spacing, so "one more time!" matches "One more time"
+pub fn normalize_meaning(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// IME input may give full-width digits
+fn normalize_number(text: &str) -> String {
+    text.chars()
+        .filter_map(|c| match c {
+            '0'..='9' => Some(c),
+            '０'..='９' => char::from_u32(c as u32 - '０' as u32 + '0' as u32),
+            _ => None,
+        })
+        .collect::<String>()
+        .trim_start_matches('0')
+        .to_string()
+}
+
+// IME input can leave spaces around the committed kanji
+fn normalize_exact(text: &str) -> String {
+    text.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+pub fn normalizer(practice_type: PracticeType) -> fn(&str) -> String {
+    match practice_type {
+        PracticeType::Lookalike | PracticeType::Production(_) => normalize_exact,
+        PracticeType::Meaning | PracticeType::Jukugo(JukugoVariant::Meaning) => normalize_meaning,
+        PracticeType::Onyomi => normalize_romaji,
+        PracticeType::Kunyomi | PracticeType::Jukugo(JukugoVariant::Reading) => normalize_kana,
+        PracticeType::StrokeCount => normalize_number,
+    }
+}
+
+// The user's blocked answers win over everything, then their accepted ones, then the scraped answers
+pub fn grade(card: &PracticeCard, answer: &str, user_answers: Option<&KanjiAnswers>) -> bool {
+    let normalize = normalizer(card.practice_type);
+    let given = normalize(answer);
+    if given.is_empty() {
+        return false;
+    }
+
+    // Jukugo cards are keyed by the kanji they were found on, so tell them apart by the word
+    let word = match card.practice_type {
+        PracticeType::Jukugo(_) => Some(strip_reading_suffix(&card.prompt)),
+        _ => None,
+    };
+    let matches = |user: &[UserAnswer]| {
+        user.iter().any(|a| {
+            a.practice_type == card.practice_type
+                && a.word.as_deref().map(strip_reading_suffix) == word
+                && normalize(&a.text) == given
+        })
+    };
+
+    if let Some(user_answers) = user_answers {
+        if matches(&user_answers.blocked) {
+            return false;
+        }
+        if matches(&user_answers.accepted) {
+            return true;
+        }
+    }
+
+    card.answers.iter().any(|expected| normalize(expected) == given)
+}
@@ -0,0 +1,345 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard};
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use super::review_log::ReviewLogState;
+use crate::parser::config::{ScraperConfig, ScraperConfigState};
+use crate::parser::flusher::FlushTarget;
+use crate::parser::http::HttpClientState;
+use crate::parser::models::{KanjiDatabase, KanjiDatabaseState, KanjiDetail, KanjiId, KanjiListing};
+use crate::parser::{breakdown_components, refresh};
+use crate::storage::{self, SECONDS_PER_DAY};
+use crate::user_data::history::{Change, HistoryState};
+
+pub const LESSON_CONFIG_PATH: &str = "lesson_config.json";
+pub const LESSON_LOG_PATH: &str = "lessons.json";
+
+// Uncached pages fetched per lesson at most, so skipping lots of kanji can't turn one call
+// into a long scrape. Kanji past the limit are picked up by later calls as pages get cached.
+const MAX_LESSON_FETCHES: usize = 5;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct LessonConfig {
+    // New kanji introduced per day
+    pub daily_cap: usize,
+    pub skip_radicals: bool,
+    // Skip kanji with fewer usefulness stars than this
+    pub min_usefulness: u8,
+    // Hold kanji back until every component they're built from is learned
+    pub require_components: bool,
+    // Correct answers in a row a component needs before it counts as learned
+    pub component_mastery: u32,
+}
+
+impl Default for LessonConfig {
+    fn default() -> Self {
+        Self {
+            daily_cap: 10,
+            skip_radicals: false,
+            min_usefulness: 0,
+            require_components: false,
+            component_mastery: 3,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Enrollment {
+    pub kanji_id: KanjiId,
+    pub enrolled_at: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Lesson {
+    pub kanjis: Vec<KanjiDetail>,
+    pub enrolled_today: usize,
+    pub daily_cap: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Prerequisite {
+    pub kanji_id: KanjiId,
+    pub kanji: String,
+    pub enrolled: bool,
+    pub mastery: u32,
+    pub met: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UnlockStatus {
+    pub kanji_id: KanjiId,
+    pub unlocked: bool,
+    pub required_mastery: u32,
+    pub prerequisites: Vec<Prerequisite>,
+}
+
+pub struct Lessons {
+    pub config: LessonConfig,
+    pub enrollments: Vec<Enrollment>,
+}
+
+impl Lessons {
+    pub fn load() -> Result<Self, String> {
+        Ok(Self {
+            config: storage::read_json(LESSON_CONFIG_PATH)?.unwrap_or_default(),
+            enrollments: storage::read_json(LESSON_LOG_PATH)?.unwrap_or_default(),
+        })
+    }
+
+    // Days are counted in UTC
+    pub fn enrolled_today(&self) -> usize {
+        let today = storage::unix_now() / SECONDS_PER_DAY;
+        self.enrollments
+            .iter()
+            .filter(|e| e.enrolled_at / SECONDS_PER_DAY == today)
+            .count()
+    }
+
+    pub fn remaining_today(&self) -> usize {
+        self.config.daily_cap.saturating_sub(self.enrolled_today())
+    }
+}
+
+// The kanji `detail` is built from: links in its breakdown, plus any cached kanji that
+// lists it under "used in"
+fn component_ids(db: &KanjiDatabase, detail: &KanjiDetail) -> Vec<KanjiId> {
+    let mut components = breakdown_components(&detail.breakdown);
+
+    for other in db.details.values() {
+        let uses_detail = other.used_in
+            .iter()
+            .any(|u| u.link.as_ref().is_some_and(|link| link.index == detail.index));
+        if uses_detail && !components.iter().any(|c| c.index == other.index) {
+            components.push(other.link.clone());
+        }
+    }
+
+    components.retain(|c| c.index != detail.index);
+    components
+}
+
+fn prerequisites(
+    db: &KanjiDatabase,
+    detail: &KanjiDetail,
+    streaks: &HashMap<KanjiId, u32>,
+    config: &LessonConfig,
+    scraper_config: &ScraperConfig
+) -> Vec<Prerequisite> {
+    component_ids(db, detail)
+        .into_iter()
+        .filter_map(|kanji_id| {
+            // Components without a kanji list entry have no lesson of their own, so they can't block.
+            // Neither can skipped radicals, they're never enrolled.
+            let listing = KanjiListing::search_by_index(&db.kanjis, kanji_id.index as i32)?;
+            if config.skip_radicals && listing.is_radical {
+                return None;
+            }
+            let mastery = streaks.get(&listing.link).copied().unwrap_or(0);
+
+            Some(Prerequisite {
+                kanji_id: listing.link.clone(),
+                kanji: scraper_config.resolve_listing(listing.clone()).kanji,
+                enrolled: listing.practice,
+                mastery,
+                met: listing.practice && mastery >= config.component_mastery,
+            })
+        })
+        .collect()
+}
+
+pub struct LessonState(Mutex<Lessons>);
+
+impl LessonState {
+    pub fn new(lessons: Lessons) -> Self {
+        Self(Mutex::new(lessons))
+    }
+
+    pub fn lock(&self) -> Result<MutexGuard<'_, Lessons>, String> {
+        self.0.lock().map_err(|_| "Failed to lock lessons".to_string())
+    }
+}
+
+#[tauri::command]
+pub fn get_lesson_config(lessons: State<'_, LessonState>) -> Result<LessonConfig, String> {
+    Ok(lessons.lock()?.config.clone())
+}
+
+#[tauri::command]
+pub fn update_lesson_config(lessons: State<'_, LessonState>, config: LessonConfig) -> Result<(), String> {
+    storage::write_json(LESSON_CONFIG_PATH, &config)?;
+    lessons.lock()?.config = config;
+    Ok(())
+}
+
+// The next kanji to learn, in KanjiDamage order, up to what's left of today's quota.
+// Nothing is enrolled until enroll_lesson is called, so the lesson can be read first.
+#[tauri::command]
+pub async fn get_next_lesson(
+    state: State<'_, KanjiDatabaseState>,
+    http: State<'_, HttpClientState>,
+    scraper_config: State<'_, ScraperConfigState>,
+    lessons: State<'_, LessonState>,
+    review_log: State<'_, ReviewLogState>
+) -> Result<Lesson, String> {
+    let (config, enrolled_today, remaining) = {
+        let lessons = lessons.lock()?;
+        (lessons.config.clone(), lessons.enrolled_today(), lessons.remaining_today())
+    };
+    let scraper_config = scraper_config.read()?.clone();
+    let streaks = if config.require_components {
+        review_log.lock()?.correct_streaks()
+    } else {
+        HashMap::new()
+    };
+
+    let mut candidates: Vec<(i32, KanjiId)> = state.read()?
+        .kanjis
+        .iter()
+        .filter(|k| !k.practice && (!config.skip_radicals || !k.is_radical))
+        .map(|k| (k.index, k.link.clone()))
+        .collect();
+    candidates.sort_by_key(|(index, _)| *index);
+
+    let mut kanjis = Vec::new();
+    let mut fetches = 0;
+    for (_, kanji_id) in candidates {
+        if kanjis.len() >= remaining {
+            break;
+        }
+
+        // Usefulness and components are only on the detail page, fetch the ones we haven't
+        // cached yet. Once the fetch limit is hit only cached kanji are considered.
+        let cached = state.read()?.detail(&kanji_id).cloned();
+        let detail = match cached {
+            Some(detail) => detail,
+            None if fetches < MAX_LESSON_FETCHES => {
+                fetches += 1;
+                let client = http.client()?;
+                // A page that fails to load is left for a later lesson
+                match refresh::refresh_page(&state, &client, &scraper_config, &kanji_id).await {
+                    Ok(detail) => detail,
+                    Err(e) => {
+                        eprintln!("Skipping {} in lesson: {}", kanji_id, e);
+                        continue;
+                    }
+                }
+            }
+            None => continue,
+        };
+
+        if detail.usefulness < config.min_usefulness {
+            continue;
+        }
+        if config.require_components {
+            let db = state.read()?;
+            let locked = prerequisites(&db, &detail, &streaks, &config, &scraper_config)
+                .iter()
+                .any(|p| !p.met);
+            if locked {
+                continue;
+            }
+        }
+
+        kanjis.push(scraper_config.resolve_detail(detail));
+    }
+
+    Ok(Lesson {
+        kanjis,
+        enrolled_today,
+        daily_cap: config.daily_cap,
+    })
+}
+
+// Which components a kanji is waiting on before it can show up in a lesson
+#[tauri::command]
+pub async fn get_lesson_prerequisites(
+    state: State<'_, KanjiDatabaseState>,
+    http: State<'_, HttpClientState>,
+    scraper_config: State<'_, ScraperConfigState>,
+    lessons: State<'_, LessonState>,
+    review_log: State<'_, ReviewLogState>,
+    url: String
+) -> Result<UnlockStatus, String> {
+    let kanji_id = KanjiId::parse(&url).ok_or_else(|| format!("Invalid kanji link: {}", url))?;
+    let scraper_config = scraper_config.read()?.clone();
+    let config = lessons.lock()?.config.clone();
+
+    let cached = state.read()?.detail(&kanji_id).cloned();
+    let detail = match cached {
+        Some(detail) => detail,
+        None => {
+            let client = http.client()?;
+            refresh::refresh_page(&state, &client, &scraper_config, &kanji_id).await?
+        }
+    };
+
+    let streaks = review_log.lock()?.correct_streaks();
+    let db = state.read()?;
+    let prerequisites = prerequisites(&db, &detail, &streaks, &config, &scraper_config);
+
+    Ok(UnlockStatus {
+        kanji_id: detail.link,
+        unlocked: prerequisites.iter().all(|p| p.met),
+        required_mastery: config.component_mastery,
+        prerequisites,
+    })
+}
+
+// Put the kanji from a finished lesson into reviews. Kanji beyond today's quota are left
+// for tomorrow; returns the ones that were enrolled. Undoing only takes them back out of
+// practice, they still count towards today's quota.
+#[tauri::command]
+pub fn enroll_lesson(
+    state: State<'_, KanjiDatabaseState>,
+    lessons: State<'_, LessonState>,
+    history: State<'_, HistoryState>,
+    kanji_ids: Vec<String>
+) -> Result<Vec<KanjiId>, String> {
+    let mut lessons = lessons.lock()?;
+    let remaining = lessons.remaining_today();
+
+    let mut seen = HashSet::new();
+    let kanji_ids: Vec<KanjiId> = kanji_ids
+        .iter()
+        .map(|link| KanjiId::parse(link).ok_or_else(|| format!("Invalid kanji link: {}", link)))
+        .collect::<Result<Vec<_>, String>>()?
+        .into_iter()
+        .filter(|kanji_id| seen.insert(kanji_id.clone()))
+        .collect();
+
+    let mut enrolled = Vec::new();
+    {
+        let mut db = state.write()?;
+        for kanji_id in kanji_ids {
+            if enrolled.len() >= remaining {
+                break;
+            }
+            if db.is_practiced(kanji_id.index) {
+                continue;
+            }
+
+            db.set_practice(kanji_id.index, true)?;
+            enrolled.push(kanji_id);
+        }
+    }
+    state.mark_dirty(&[FlushTarget::Listings, FlushTarget::Details]);
+
+    let enrolled_at = storage::unix_now();
+    lessons.enrollments.extend(enrolled.iter().map(|kanji_id| Enrollment {
+        kanji_id: kanji_id.clone(),
+        enrolled_at,
+    }));
+    storage::write_json(LESSON_LOG_PATH, &lessons.enrollments)?;
+
+    if !enrolled.is_empty() {
+        history.record(format!("Enroll {} kanji from a lesson", enrolled.len()), Change::Practice {
+            indexes: enrolled.iter().map(|kanji_id| kanji_id.index).collect(),
+            practice: true,
+        })?;
+    }
+
+    Ok(enrolled)
+}
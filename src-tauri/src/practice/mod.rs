@@ -0,0 +1,491 @@
+pub mod bulk;
+pub mod choices;
+pub mod fsrs;
+pub mod grading;
+pub mod lessons;
+pub mod models;
+pub mod production;
+pub mod review_log;
+pub mod scheduler;
+pub mod session;
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use tauri::State;
+
+use crate::dictionary::kanjidic::{KanjiMetadataMap, KanjidicState};
+use crate::dictionary::kanjivg::{KanjiStrokesMap, KanjivgState};
+use crate::parser::config::{ScraperConfig, ScraperConfigState};
+use crate::parser::models::{KanjiDatabase, KanjiDatabaseState, KanjiDetail, KanjiId, KunyomiEntry};
+use crate::storage;
+use crate::text::strip_reading_suffix;
+use crate::user_data::answers::UserAnswersState;
+use crate::user_data::lists::{StudyLists, StudyListsState};
+use choices::{add_choices, ChoiceOptions, DistractorIndex, DEFAULT_CHOICE_COUNT};
+use models::{CardFilter, Choice, JukugoVariant, PracticeCard, PracticeType, Rating, ReviewEntry};
+use production::{accept_matching_kanji, production_card};
+use review_log::{confusion_pair, ReviewLog, ReviewLogState};
+use scheduler::{ReviewQueue, Scheduler, SchedulerState};
+
+// Extra copies of a lookalike card per recorded confusion, capped so one bad pair can't take over
+const MAX_CONFUSION_REPEATS: u32 = 2;
+
+// Split combined readings like "ICHI, ITSU"
+pub fn onyomi_readings(detail: &KanjiDetail) -> Vec<String> {
+    detail.onyomi
+        .iter()
+        .flat_map(|(reading, _)| reading.split(','))
+        .map(|r| r.trim().to_string())
+        .filter(|r| !r.is_empty())
+        .collect()
+}
+
+// Jukugo English often lists several meanings, accept each one on its own
+fn split_meanings(english: &str) -> Vec<String> {
+    english.split([',', ';', '/'])
+        .map(|m| m.trim().to_string())
+        .filter(|m| !m.is_empty())
+        .collect()
+}
+
+pub fn build_cards<'a>(
+    details: impl IntoIterator<Item = &'a KanjiDetail>,
+    practice_type: PracticeType,
+    filter: &CardFilter,
+) -> Vec<PracticeCard> {
+    let mut cards = Vec::new();
+    // The same word shows up under each of its kanji, only ask it once
+    let mut seen_words = HashSet::new();
+
+    for detail in details {
+        let kanji_card = |answers: Vec<String>| PracticeCard {
+            kanji_id: detail.link.clone(),
+            practice_type,
+            prompt: detail.kanji.clone(),
+            answers,
+            choices: Vec::new(),
+            hint: None,
+            usefulness: detail.usefulness,
+        };
+
+        match practice_type {
+            PracticeType::Meaning => cards.push(kanji_card(detail.meanings.clone())),
+            PracticeType::Onyomi => cards.push(kanji_card(onyomi_readings(detail))),
+            PracticeType::Kunyomi => {
+                // Only asked when a reading is useful enough, but any reading is a right answer
+                let mut kunyomi: Vec<&KunyomiEntry> = detail.kunyomi.iter().collect();
+                kunyomi.sort_by_key(|k| Reverse(k.usefulness));
+
+                let Some(best) = kunyomi.first().map(|k| k.usefulness) else {
+                    continue;
+                };
+                if best < filter.min_kunyomi_usefulness {
+                    continue;
+                }
+
+                cards.push(PracticeCard {
+                    usefulness: best,
+                    ..kanji_card(kunyomi.iter().map(|k| k.reading.clone()).collect())
+                });
+            }
+            PracticeType::Jukugo(variant) => {
+                for jukugo in &detail.jukugo {
+                    if jukugo.usefulness < filter.min_usefulness {
+                        continue;
+                    }
+                    let tagged = filter.jukugo_tags.iter().any(|tag| jukugo.has_tag(tag));
+                    if !filter.jukugo_tags.is_empty() && !tagged {
+                        continue;
+                    }
+
+                    let word = strip_reading_suffix(&jukugo.japanese).to_string();
+                    if !seen_words.insert(word.clone()) {
+                        continue;
+                    }
+
+                    let (answers, hint) = match variant {
+                        JukugoVariant::Reading => (vec![jukugo.reading.clone()], jukugo.english.clone()),
+                        JukugoVariant::Meaning => (split_meanings(&jukugo.english), jukugo.reading.clone()),
+                    };
+
+                    cards.push(PracticeCard {
+                        kanji_id: detail.link.clone(),
+                        practice_type,
+                        prompt: word,
+                        answers,
+                        choices: Vec::new(),
+                        hint: Some(hint),
+                        usefulness: jukugo.usefulness,
+                    });
+                }
+            }
+            PracticeType::Lookalike => {
+                if let Some(card) = lookalike_card(detail) {
+                    cards.push(card);
+                }
+            }
+            PracticeType::Production(prompt) => {
+                if let Some(card) = production_card(detail, prompt) {
+                    cards.push(card);
+                }
+            }
+            // Needs the imported stroke data, see session_cards
+            PracticeType::StrokeCount => {}
+        }
+    }
+
+    // Kanji without e.g. a kunyomi have nothing to ask
+    cards.retain(|card| !card.answers.is_empty());
+    cards
+}
+
+// Ask for the kanji by its meaning, with its lookalikes as the other options
+fn lookalike_card(detail: &KanjiDetail) -> Option<PracticeCard> {
+    if detail.lookalikes.is_empty() {
+        return None;
+    }
+
+    let mut choices = vec![Choice {
+        text: detail.kanji.clone(),
+        kanji_id: Some(detail.link.clone()),
+    }];
+    // The lookalike table usually lists the kanji itself too
+    for lookalike in &detail.lookalikes {
+        if choices.iter().all(|c| c.text != lookalike.kanji) {
+            choices.push(Choice {
+                text: lookalike.kanji.clone(),
+                kanji_id: lookalike.kanji_link.clone(),
+            });
+        }
+    }
+
+    if choices.len() < 2 {
+        return None;
+    }
+
+    let hint = detail.lookalikes
+        .iter()
+        .map(|l| format!("{}: {}", l.kanji, l.hint.trim()))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Some(PracticeCard {
+        kanji_id: detail.link.clone(),
+        practice_type: PracticeType::Lookalike,
+        prompt: detail.meanings.join(", "),
+        answers: vec![detail.kanji.clone()],
+        choices,
+        hint: Some(hint),
+        usefulness: detail.usefulness,
+    })
+}
+
+fn stroke_count_card(detail: &KanjiDetail, count: usize) -> PracticeCard {
+    PracticeCard {
+        kanji_id: detail.link.clone(),
+        practice_type: PracticeType::StrokeCount,
+        prompt: detail.kanji.clone(),
+        answers: vec![count.to_string()],
+        choices: Vec::new(),
+        hint: None,
+        usefulness: detail.usefulness,
+    }
+}
+
+// Repeat cards whose kanji the user keeps mixing up with one of the options
+fn repeat_confused(cards: Vec<PracticeCard>, confusions: &HashMap<(KanjiId, KanjiId), u32>) -> Vec<PracticeCard> {
+    let mut repeated = Vec::with_capacity(cards.len());
+
+    for card in cards {
+        let confused: u32 = card.choices
+            .iter()
+            .filter_map(|choice| choice.kanji_id.as_ref())
+            .filter(|kanji_id| **kanji_id != card.kanji_id)
+            .filter_map(|kanji_id| confusions.get(&confusion_pair(&card.kanji_id, kanji_id)))
+            .sum();
+
+        for _ in 0..confused.min(MAX_CONFUSION_REPEATS) {
+            repeated.push(card.clone());
+        }
+        repeated.push(card);
+    }
+
+    repeated
+}
+
+// Cards for every practiced kanji that's due or new, before shuffling and link resolution
+#[allow(clippy::too_many_arguments)]
+fn session_cards(
+    db: &KanjiDatabase,
+    review_log: &ReviewLog,
+    scheduler: &Scheduler,
+    lists: &StudyLists,
+    metadata: &KanjiMetadataMap,
+    strokes: &KanjiStrokesMap,
+    practice_type: PracticeType,
+    filter: &CardFilter,
+    now: u64
+) -> Vec<PracticeCard> {
+    let members = lists.members(&filter.lists);
+    let details = db.details
+        .values()
+        .filter(|d| match &members {
+            Some(members) => members.contains(&d.index),
+            None => d.practice,
+        })
+        .filter(|d| filter.tags.is_empty() || filter.tags.iter().any(|tag| d.has_tag(tag)))
+        .filter(|d| filter.metadata.matches(metadata.get(&d.link)))
+        .filter(|d| !scheduler.is_suspended(&d.link))
+        .filter(|d| scheduler.queue(&d.link, now).is_some());
+
+    if practice_type == PracticeType::StrokeCount {
+        // KanjiVG's strokes when imported, KANJIDIC2's count otherwise
+        return details
+            .filter_map(|d| {
+                let count = strokes.get(&d.link).map(Vec::len)
+                    .or_else(|| metadata.get(&d.link)?.stroke_count.map(usize::from))?;
+                Some(stroke_count_card(d, count))
+            })
+            .collect();
+    }
+
+    let mut cards = build_cards(details, practice_type, filter);
+    match practice_type {
+        PracticeType::Lookalike => cards = repeat_confused(cards, &review_log.confusion_counts()),
+        PracticeType::Production(_) => accept_matching_kanji(&mut cards, db),
+        _ => {}
+    }
+
+    cards
+}
+
+// Shuffle the cards, or session items, within their review queue and cut them down to
+// `max_cards`. Weighted by usefulness, each one draws u^(1 / (stars + 1)) and the highest draws
+// go first, so more stars means earlier on average.
+fn order_by_usefulness<T>(
+    items: &mut Vec<T>,
+    queue: impl Fn(&T) -> ReviewQueue,
+    usefulness: impl Fn(&T) -> u8,
+    filter: &CardFilter,
+    rng: &mut impl Rng
+) {
+    if filter.weight_by_usefulness {
+        let mut keyed: Vec<(ReviewQueue, f64, T)> = items
+            .drain(..)
+            .map(|item| (queue(&item), rng.gen::<f64>().powf(1.0 / (usefulness(&item) as f64 + 1.0)), item))
+            .collect();
+        keyed.sort_by(|(queue_a, a, _), (queue_b, b, _)| queue_a.cmp(queue_b).then(b.total_cmp(a)));
+        items.extend(keyed.into_iter().map(|(_, _, item)| item));
+    } else {
+        items.shuffle(rng);
+        items.sort_by_key(|item| queue(item));
+    }
+
+    if let Some(max_cards) = filter.max_cards {
+        items.truncate(max_cards);
+    }
+}
+
+// Image kanji are stored as site paths, turn them into URLs the frontend can load
+fn resolve_card(mut card: PracticeCard, config: &ScraperConfig) -> PracticeCard {
+    card.prompt = config.resolve(&card.prompt);
+    for answer in &mut card.answers {
+        *answer = config.resolve(answer);
+    }
+    for choice in &mut card.choices {
+        choice.text = config.resolve(&choice.text);
+    }
+    card
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub fn build_practice_cards(
+    state: State<'_, KanjiDatabaseState>,
+    scraper_config: State<'_, ScraperConfigState>,
+    review_log: State<'_, ReviewLogState>,
+    scheduler: State<'_, SchedulerState>,
+    lists: State<'_, StudyListsState>,
+    kanjidic: State<'_, KanjidicState>,
+    kanjivg: State<'_, KanjivgState>,
+    practice_type: PracticeType,
+    filter: Option<CardFilter>
+) -> Result<Vec<PracticeCard>, String> {
+    let filter = filter.unwrap_or_default();
+    let db = state.read()?;
+    let config = scraper_config.read()?;
+
+    let review_log = review_log.lock()?;
+    let scheduler = scheduler.lock()?;
+    let lists = lists.read()?;
+    let metadata = kanjidic.read()?;
+    let strokes = kanjivg.read()?;
+    let now = storage::unix_now();
+    let cards = session_cards(&db, &review_log, &scheduler, &lists, &metadata, &strokes, practice_type, &filter, now);
+
+    let mut cards: Vec<PracticeCard> = cards
+        .into_iter()
+        .map(|card| resolve_card(card, &config))
+        .collect();
+    order_by_usefulness(
+        &mut cards,
+        |card| scheduler.queue(&card.kanji_id, now).unwrap_or(ReviewQueue::New),
+        |card| card.usefulness,
+        &filter,
+        &mut rand::thread_rng(),
+    );
+    Ok(cards)
+}
+
+// Same as build_practice_cards, with every card turned into a multiple-choice question.
+// Passing a seed replays the exact same session.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub fn build_choice_cards(
+    state: State<'_, KanjiDatabaseState>,
+    scraper_config: State<'_, ScraperConfigState>,
+    review_log: State<'_, ReviewLogState>,
+    scheduler: State<'_, SchedulerState>,
+    lists: State<'_, StudyListsState>,
+    kanjidic: State<'_, KanjidicState>,
+    kanjivg: State<'_, KanjivgState>,
+    practice_type: PracticeType,
+    filter: Option<CardFilter>,
+    options: Option<ChoiceOptions>
+) -> Result<Vec<PracticeCard>, String> {
+    let filter = filter.unwrap_or_default();
+    let options = options.unwrap_or_default();
+    let choice_count = options.choice_count.unwrap_or(DEFAULT_CHOICE_COUNT).max(2);
+    let mut rng = match options.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    let db = state.read()?;
+    let config = scraper_config.read()?;
+
+    let review_log = review_log.lock()?;
+    let scheduler = scheduler.lock()?;
+    let lists = lists.read()?;
+    let metadata = kanjidic.read()?;
+    let strokes = kanjivg.read()?;
+    let now = storage::unix_now();
+    let mut cards =
+        session_cards(&db, &review_log, &scheduler, &lists, &metadata, &strokes, practice_type, &filter, now);
+
+    let index = DistractorIndex::new(&db);
+    for card in &mut cards {
+        add_choices(card, &index, choice_count, &mut rng);
+    }
+    // Drop cards the cache couldn't find a single wrong option for
+    cards.retain(|card| card.choices.len() >= 2);
+
+    let mut cards: Vec<PracticeCard> = cards
+        .into_iter()
+        .map(|card| resolve_card(card, &config))
+        .collect();
+    order_by_usefulness(
+        &mut cards,
+        |card| scheduler.queue(&card.kanji_id, now).unwrap_or(ReviewQueue::New),
+        |card| card.usefulness,
+        &filter,
+        &mut rng,
+    );
+    Ok(cards)
+}
+
+#[tauri::command]
+pub fn grade_practice_answer(
+    user_answers: State<'_, UserAnswersState>,
+    card: PracticeCard,
+    answer: String
+) -> Result<bool, String> {
+    Ok(grading::grade(&card, &answer, user_answers.read()?.get(&card.kanji_id)))
+}
+
+// Grade an answer, record it in the review log and reschedule the kanji
+#[tauri::command]
+pub fn submit_practice_answer(
+    review_log: State<'_, ReviewLogState>,
+    scheduler: State<'_, SchedulerState>,
+    user_answers: State<'_, UserAnswersState>,
+    card: PracticeCard,
+    answer: String
+) -> Result<bool, String> {
+    let entry = answer_entry(&user_answers, &card, &answer)?;
+    let correct = entry.correct;
+    record_review(&review_log, &scheduler, entry)?;
+    Ok(correct)
+}
+
+// Grade a typed or picked answer into a review log entry
+fn answer_entry(user_answers: &UserAnswersState, card: &PracticeCard, answer: &str) -> Result<ReviewEntry, String> {
+    let correct = grading::grade(card, answer, user_answers.read()?.get(&card.kanji_id));
+
+    // Picking another kanji's option counts as confusing the two
+    let confused_with = if correct {
+        None
+    } else {
+        card.choices
+            .iter()
+            .find(|choice| choice.text == answer.trim())
+            .and_then(|choice| choice.kanji_id.clone())
+            .filter(|kanji_id| *kanji_id != card.kanji_id)
+    };
+
+    Ok(ReviewEntry {
+        kanji_id: card.kanji_id.clone(),
+        practice_type: card.practice_type,
+        prompt: card.prompt.clone(),
+        answer: answer.to_string(),
+        correct,
+        answered_at: storage::unix_now(),
+        confused_with,
+        rating: None,
+    })
+}
+
+// Record how well the user says they recalled a revealed answer; anything but Again counts as correct
+#[tauri::command]
+pub fn submit_practice_rating(
+    review_log: State<'_, ReviewLogState>,
+    scheduler: State<'_, SchedulerState>,
+    card: PracticeCard,
+    rating: Rating
+) -> Result<bool, String> {
+    let entry = rating_entry(&card, rating);
+    let correct = entry.correct;
+    record_review(&review_log, &scheduler, entry)?;
+    Ok(correct)
+}
+
+fn rating_entry(card: &PracticeCard, rating: Rating) -> ReviewEntry {
+    ReviewEntry {
+        kanji_id: card.kanji_id.clone(),
+        practice_type: card.practice_type,
+        prompt: card.prompt.clone(),
+        answer: String::new(),
+        correct: rating != Rating::Again,
+        answered_at: storage::unix_now(),
+        confused_with: None,
+        rating: Some(rating),
+    }
+}
+
+fn record_review(review_log: &ReviewLogState, scheduler: &SchedulerState, entry: ReviewEntry) -> Result<(), String> {
+    let mut review_log = review_log.lock()?;
+    let mut scheduler = scheduler.lock()?;
+
+    let confusions = review_log.confusions_of(&entry.kanji_id);
+    scheduler.review(&entry.kanji_id, entry.rating(), entry.answered_at, confusions);
+    if let Some(other) = &entry.confused_with {
+        scheduler.bring_forward(other, entry.answered_at);
+    }
+    scheduler.save()?;
+
+    review_log.record(entry)
+}
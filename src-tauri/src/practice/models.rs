@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+
+use crate::dictionary::kanjidic::MetadataFilter;
+use crate::parser::models::KanjiId;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PracticeType {
+    Meaning,
+    Onyomi,
+    Kunyomi,
+    Jukugo(JukugoVariant),
+    // Meaning -> pick the kanji out of it and its lookalikes
+    Lookalike,
+    // Meaning or readings -> type or pick the kanji
+    Production(ProductionPrompt),
+    // Kanji -> how many strokes it's written with, once KanjiVG or KANJIDIC2 is imported
+    StrokeCount,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProductionPrompt {
+    // The kanji's main English meaning
+    Meaning,
+    // Its onyomi and kunyomi
+    Reading,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JukugoVariant {
+    // Word -> kana reading
+    Reading,
+    // Word -> English
+    Meaning,
+}
+
+// A single question: show `prompt`, accept any of `answers`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PracticeCard {
+    // The practiced kanji the card was built from
+    pub kanji_id: KanjiId,
+    pub practice_type: PracticeType,
+    pub prompt: String,
+    pub answers: Vec<String>,
+    // Options to pick from; empty when the answer is typed
+    #[serde(default)]
+    pub choices: Vec<Choice>,
+    // Shown once the card is answered, e.g. a jukugo's English when drilling its reading
+    pub hint: Option<String>,
+    // Stars of what's asked: the word for jukugo, the best reading for kunyomi, otherwise the kanji
+    #[serde(default)]
+    pub usefulness: u8,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Choice {
+    pub text: String,
+    // The kanji this option belongs to, so a wrong pick can be recorded as a confusion
+    pub kanji_id: Option<KanjiId>,
+}
+
+// How well an answer was recalled, as fed to the scheduler. Ordered worst to best.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Rating {
+    Again,
+    Hard,
+    Good,
+    Easy,
+}
+
+impl Rating {
+    // Typed answers are either right or wrong
+    pub fn from_correct(correct: bool) -> Self {
+        if correct { Rating::Good } else { Rating::Again }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReviewEntry {
+    pub kanji_id: KanjiId,
+    pub practice_type: PracticeType,
+    pub prompt: String,
+    pub answer: String,
+    pub correct: bool,
+    pub answered_at: u64,
+    // The kanji picked instead of the right one
+    pub confused_with: Option<KanjiId>,
+    // Set when the user rated their own recall instead of typing an answer
+    #[serde(default)]
+    pub rating: Option<Rating>,
+}
+
+impl ReviewEntry {
+    pub fn rating(&self) -> Rating {
+        self.rating.unwrap_or(Rating::from_correct(self.correct))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct CardFilter {
+    // Skip jukugo with fewer usefulness stars than this
+    pub min_usefulness: u8,
+    // Only ask kunyomi with at least this many stars; the others are still accepted as answers
+    pub min_kunyomi_usefulness: u8,
+    // Draw cards with more stars first, so they're the ones kept when `max_cards` cuts the pool
+    pub weight_by_usefulness: bool,
+    // Sessions count kanji rather than questions, so a kanji is never asked only in part
+    pub max_cards: Option<usize>,
+    #[serde(flatten)]
+    pub metadata: MetadataFilter,
+    // Only practice kanji in these study lists, whether or not they're in the practice pool
+    pub lists: Vec<u64>,
+    // Only practice kanji carrying one of these tags
+    pub tags: Vec<String>,
+    // Only ask jukugo carrying one of these tags
+    pub jukugo_tags: Vec<String>,
+}
@@ -0,0 +1,142 @@
+use std::collections::{HashMap, HashSet};
+
+use super::grading::{normalize_meaning, normalize_romaji};
+use super::models::{PracticeCard, PracticeType, ProductionPrompt};
+use super::onyomi_readings;
+use crate::parser::models::{KanjiDatabase, KanjiDetail, KanjiId};
+use crate::text::normalize_kana;
+
+// Kanji only available as an image can't be typed
+fn is_typeable(detail: &KanjiDetail) -> bool {
+    !detail.kanji.is_empty() && !detail.kanji.starts_with('/')
+}
+
+// Onyomi and kunyomi normalized the way grading compares them
+fn reading_keys(detail: &KanjiDetail) -> HashSet<String> {
+    onyomi_readings(detail)
+        .iter()
+        .map(|r| normalize_romaji(r))
+        .chain(detail.kunyomi.iter().map(|k| normalize_kana(&k.reading)))
+        .filter(|r| !r.is_empty())
+        .collect()
+}
+
+fn meaning_keys(detail: &KanjiDetail) -> HashSet<String> {
+    detail.meanings
+        .iter()
+        .map(|m| normalize_meaning(m))
+        .filter(|m| !m.is_empty())
+        .collect()
+}
+
+pub fn production_card(detail: &KanjiDetail, prompt: ProductionPrompt) -> Option<PracticeCard> {
+    if !is_typeable(detail) {
+        return None;
+    }
+
+    let (prompt_text, hint) = match prompt {
+        ProductionPrompt::Meaning => {
+            let (primary, rest) = detail.meanings.split_first()?;
+            (primary.clone(), (!rest.is_empty()).then(|| rest.join(", ")))
+        }
+        ProductionPrompt::Reading => {
+            let onyomi = onyomi_readings(detail);
+            let kunyomi: Vec<String> = detail.kunyomi.iter().map(|k| k.reading.clone()).collect();
+            if onyomi.is_empty() && kunyomi.is_empty() {
+                return None;
+            }
+
+            let readings = [onyomi.join(", "), kunyomi.join(", ")]
+                .into_iter()
+                .filter(|r| !r.is_empty())
+                .collect::<Vec<_>>()
+                .join(" / ");
+            (readings, Some(detail.meanings.join(", ")))
+        }
+    };
+
+    Some(PracticeCard {
+        kanji_id: detail.link.clone(),
+        practice_type: PracticeType::Production(prompt),
+        prompt: prompt_text,
+        answers: vec![detail.kanji.clone()],
+        choices: Vec::new(),
+        hint,
+        usefulness: detail.usefulness,
+    })
+}
+
+// Typeable kanji by each of their normalized meanings and readings, built once per batch of cards
+struct MatchIndex<'a> {
+    by_meaning: HashMap<String, Vec<&'a KanjiDetail>>,
+    by_reading: HashMap<String, Vec<&'a KanjiDetail>>,
+    readings: HashMap<&'a KanjiId, HashSet<String>>,
+}
+
+impl<'a> MatchIndex<'a> {
+    fn new(db: &'a KanjiDatabase) -> Self {
+        let mut by_meaning: HashMap<String, Vec<&KanjiDetail>> = HashMap::new();
+        let mut by_reading: HashMap<String, Vec<&KanjiDetail>> = HashMap::new();
+        let mut readings = HashMap::new();
+
+        for detail in db.details.values().filter(|d| is_typeable(d)) {
+            for meaning in meaning_keys(detail) {
+                by_meaning.entry(meaning).or_default().push(detail);
+            }
+            let keys = reading_keys(detail);
+            for reading in &keys {
+                by_reading.entry(reading.clone()).or_default().push(detail);
+            }
+            readings.insert(&detail.link, keys);
+        }
+
+        Self { by_meaning, by_reading, readings }
+    }
+
+    // Kanji with the meaning the card prompts for
+    fn by_meaning(&self, card: &PracticeCard) -> Vec<&'a KanjiDetail> {
+        self.by_meaning.get(&normalize_meaning(&card.prompt)).cloned().unwrap_or_default()
+    }
+
+    // Kanji with every reading of `detail`, and maybe more
+    fn by_readings(&self, detail: &KanjiDetail) -> Vec<&'a KanjiDetail> {
+        let wanted = reading_keys(detail);
+        let Some(first) = wanted.iter().next() else {
+            return Vec::new();
+        };
+
+        self.by_reading
+            .get(first)
+            .into_iter()
+            .flatten()
+            .filter(|other| self.readings.get(&other.link).is_some_and(|keys| wanted.is_subset(keys)))
+            .copied()
+            .collect()
+    }
+}
+
+// Several kanji share an English meaning or a set of readings, so accept any cached kanji
+// the prompt describes equally well, not just the one the card was built from
+pub fn accept_matching_kanji(cards: &mut [PracticeCard], db: &KanjiDatabase) {
+    let index = MatchIndex::new(db);
+
+    for card in cards {
+        let PracticeType::Production(prompt) = card.practice_type else {
+            continue;
+        };
+        let Some(detail) = db.detail(&card.kanji_id) else {
+            continue;
+        };
+
+        let matches = match prompt {
+            ProductionPrompt::Meaning => index.by_meaning(card),
+            ProductionPrompt::Reading => index.by_readings(detail),
+        };
+
+        for other in matches {
+            if !card.answers.contains(&other.kanji) {
+                card.answers.push(other.kanji.clone());
+            }
+        }
+    }
+}
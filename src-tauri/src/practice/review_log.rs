@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+use super::models::ReviewEntry;
+use crate::parser::models::KanjiId;
+use crate::storage;
+
+pub const REVIEW_LOG_PATH: &str = "review_log.jsonl";
+
+pub struct ReviewLog {
+    pub entries: Vec<ReviewEntry>,
+}
+
+impl ReviewLog {
+    pub fn load() -> Result<Self, String> {
+        Ok(Self {
+            entries: storage::read_json_lines(REVIEW_LOG_PATH)?,
+        })
+    }
+
+    pub fn record(&mut self, entry: ReviewEntry) -> Result<(), String> {
+        storage::append_json_line(REVIEW_LOG_PATH, &entry)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    // How often each pair of kanji was mixed up, in either direction
+    pub fn confusion_counts(&self) -> HashMap<(KanjiId, KanjiId), u32> {
+        let mut counts = HashMap::new();
+
+        for entry in &self.entries {
+            if let Some(other) = &entry.confused_with {
+                *counts.entry(confusion_pair(&entry.kanji_id, other)).or_insert(0) += 1;
+            }
+        }
+
+        counts
+    }
+
+    // How often a kanji was mixed up with any other, in either direction
+    pub fn confusions_of(&self, kanji_id: &KanjiId) -> u32 {
+        self.entries
+            .iter()
+            .filter(|entry| entry.confused_with.is_some())
+            .filter(|entry| entry.kanji_id == *kanji_id || entry.confused_with.as_ref() == Some(kanji_id))
+            .count() as u32
+    }
+
+    // Correct answers in a row since each kanji was last missed
+    pub fn correct_streaks(&self) -> HashMap<KanjiId, u32> {
+        let mut streaks = HashMap::new();
+
+        for entry in &self.entries {
+            let streak = streaks.entry(entry.kanji_id.clone()).or_insert(0);
+            *streak = if entry.correct { *streak + 1 } else { 0 };
+        }
+
+        streaks
+    }
+}
+
+pub fn confusion_pair(a: &KanjiId, b: &KanjiId) -> (KanjiId, KanjiId) {
+    if a <= b {
+        (a.clone(), b.clone())
+    } else {
+        (b.clone(), a.clone())
+    }
+}
+
+pub struct ReviewLogState(Mutex<ReviewLog>);
+
+impl ReviewLogState {
+    pub fn new(log: ReviewLog) -> Self {
+        Self(Mutex::new(log))
+    }
+
+    pub fn lock(&self) -> Result<MutexGuard<'_, ReviewLog>, String> {
+        self.0.lock().map_err(|_| "Failed to lock review log".to_string())
+    }
+}
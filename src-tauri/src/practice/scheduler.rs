@@ -0,0 +1,531 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use super::fsrs::{self, Fsrs, FsrsParameters, MemoryState, OptimizeResult};
+use super::models::Rating;
+use super::review_log::{ReviewLog, ReviewLogState};
+use crate::parser::config::{ScraperConfig, ScraperConfigState};
+use crate::parser::models::{KanjiDatabase, KanjiDatabaseState, KanjiId, Lookalike};
+use crate::storage::{self, SECONDS_PER_DAY};
+use crate::user_data::history::{Change, HistoryState};
+
+// Where the single config lived before profiles, read once to seed the default profile
+pub const SCHEDULER_CONFIG_PATH: &str = "scheduler_config.json";
+pub const SCHEDULER_PROFILES_PATH: &str = "scheduler_profiles.json";
+pub const SCHEDULE_PATH: &str = "review_schedule.json";
+
+pub const DEFAULT_PROFILE: &str = "Default";
+
+// Each time a kanji was mixed up with a lookalike shortens its intervals, down to this fraction
+const MAX_CONFUSION_SPEEDUP: u64 = 4;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Algorithm {
+    #[default]
+    FixedIntervals,
+    Fsrs,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SchedulerConfig {
+    pub algorithm: Algorithm,
+    // Days until the next review after 1, 2, 3... correct reviews in a row
+    pub intervals_days: Vec<u64>,
+    pub fsrs: FsrsParameters,
+    // Lapses before a kanji is flagged as a leech
+    pub leech_threshold: u32,
+    pub suspend_leeches: bool,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: Algorithm::default(),
+            intervals_days: vec![1, 3, 7, 14, 30, 60, 120],
+            fsrs: FsrsParameters::default(),
+            leech_threshold: 8,
+            suspend_leeches: false,
+        }
+    }
+}
+
+impl SchedulerConfig {
+    fn interval(&self, reps: u32) -> u64 {
+        let step = (reps as usize).saturating_sub(1).min(self.intervals_days.len().saturating_sub(1));
+        self.intervals_days.get(step).copied().unwrap_or(1) * SECONDS_PER_DAY
+    }
+}
+
+// Bring kanji the user keeps confusing back sooner, but never within the same day
+fn shorten_for_confusions(interval: u64, confusions: u32) -> u64 {
+    let speedup = (1 + u64::from(confusions)).min(MAX_CONFUSION_SPEEDUP);
+    (interval / speedup).max(SECONDS_PER_DAY)
+}
+
+// Named scheduler settings, e.g. one on fixed intervals and one on FSRS. Only the active
+// profile's settings are used; the review schedule itself is shared.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SchedulerProfiles {
+    pub active: String,
+    pub profiles: BTreeMap<String, SchedulerConfig>,
+}
+
+impl Default for SchedulerProfiles {
+    fn default() -> Self {
+        Self::with_default(SchedulerConfig::default())
+    }
+}
+
+impl SchedulerProfiles {
+    fn with_default(config: SchedulerConfig) -> Self {
+        Self {
+            active: DEFAULT_PROFILE.to_string(),
+            profiles: BTreeMap::from([(DEFAULT_PROFILE.to_string(), config)]),
+        }
+    }
+
+    pub fn active(&self) -> &SchedulerConfig {
+        &self.profiles[&self.active]
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct CardSchedule {
+    pub due: u64,
+    // Correct reviews since the last lapse
+    pub reps: u32,
+    // Times the kanji was forgotten after being learned
+    pub lapses: u32,
+    pub last_review: Option<u64>,
+    pub last_lapse: Option<u64>,
+    pub leech: bool,
+    pub suspended: bool,
+    // When the leech's remediation was last shown
+    pub remediated_at: Option<u64>,
+    // Only tracked while FSRS is the active algorithm
+    pub memory: Option<MemoryState>,
+}
+
+impl CardSchedule {
+    pub fn needs_remediation(&self) -> bool {
+        self.leech && self.remediated_at < self.last_lapse
+    }
+}
+
+// Where a kanji goes in a session: leeches to relearn first, then due reviews, then new kanji
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ReviewQueue {
+    Remediation,
+    Due,
+    New,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Leech {
+    pub kanji_id: KanjiId,
+    pub kanji: String,
+    pub lapses: u32,
+    pub suspended: bool,
+    pub needs_remediation: bool,
+}
+
+// Everything needed to relearn a leech before it comes up again
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Remediation {
+    pub kanji_id: KanjiId,
+    pub kanji: String,
+    pub meanings: Vec<String>,
+    pub mnemonic: Option<String>,
+    pub breakdown: String,
+    pub lookalikes: Vec<Lookalike>,
+    pub lapses: u32,
+}
+
+pub struct Scheduler {
+    pub profiles: SchedulerProfiles,
+    pub cards: BTreeMap<KanjiId, CardSchedule>,
+}
+
+impl Scheduler {
+    pub fn load() -> Result<Self, String> {
+        let mut profiles = match storage::read_json(SCHEDULER_PROFILES_PATH)? {
+            Some(profiles) => profiles,
+            None => SchedulerProfiles::with_default(storage::read_json(SCHEDULER_CONFIG_PATH)?.unwrap_or_default()),
+        };
+        // A hand-edited file could name a profile that isn't there
+        profiles.profiles.entry(profiles.active.clone()).or_default();
+
+        Ok(Self {
+            profiles,
+            cards: storage::read_json(SCHEDULE_PATH)?.unwrap_or_default(),
+        })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::write_json(SCHEDULE_PATH, &self.cards)
+    }
+
+    pub fn save_profiles(&self) -> Result<(), String> {
+        storage::write_json(SCHEDULER_PROFILES_PATH, &self.profiles)
+    }
+
+    pub fn config(&self) -> &SchedulerConfig {
+        self.profiles.active()
+    }
+
+    // Make `name` the active profile, with `config` as its settings. Switching to FSRS rebuilds
+    // every card's memory state from the review log, so cards reviewed under fixed intervals
+    // don't start over as new.
+    fn apply_profile(&mut self, review_log: &ReviewLog, name: String, config: SchedulerConfig) -> Result<(), String> {
+        let switched_to_fsrs = config.algorithm == Algorithm::Fsrs && self.config().algorithm != Algorithm::Fsrs;
+        self.profiles.profiles.insert(name.clone(), config);
+        self.profiles.active = name;
+        self.save_profiles()?;
+
+        if switched_to_fsrs {
+            let fsrs = Fsrs::new(&self.config().fsrs);
+            let memories: Vec<(KanjiId, Option<MemoryState>)> = fsrs::review_histories(&review_log.entries)
+                .into_iter()
+                .map(|(kanji_id, history)| (kanji_id.clone(), fsrs::replay(&fsrs, &history)))
+                .collect();
+
+            for (kanji_id, memory) in memories {
+                self.cards.entry(kanji_id).or_default().memory = memory;
+            }
+            self.save()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn is_suspended(&self, kanji_id: &KanjiId) -> bool {
+        self.cards.get(kanji_id).is_some_and(|card| card.suspended)
+    }
+
+    // None while the kanji isn't due yet. A leech's remediation comes before its next review,
+    // whenever that is.
+    pub fn queue(&self, kanji_id: &KanjiId, now: u64) -> Option<ReviewQueue> {
+        let Some(card) = self.cards.get(kanji_id) else {
+            return Some(ReviewQueue::New);
+        };
+
+        if card.needs_remediation() {
+            Some(ReviewQueue::Remediation)
+        } else if card.last_review.is_none() {
+            Some(ReviewQueue::New)
+        } else if card.due <= now {
+            Some(ReviewQueue::Due)
+        } else {
+            None
+        }
+    }
+
+    // A kanji picked in place of another is asked again as soon as possible too
+    pub fn bring_forward(&mut self, kanji_id: &KanjiId, now: u64) {
+        if let Some(card) = self.cards.get_mut(kanji_id) {
+            card.due = card.due.min(now);
+        }
+    }
+
+    // A miss after the kanji was learned counts as a lapse and sends it back to the start.
+    // `confusions` is how often it was mixed up with a lookalike.
+    pub fn review(&mut self, kanji_id: &KanjiId, rating: Rating, now: u64, confusions: u32) {
+        let config = self.profiles.active();
+        let card = self.cards.entry(kanji_id.clone()).or_default();
+
+        // FSRS would raise stability again for every pass on the same day, so only the first
+        // one counts. Misses still do.
+        let same_day = card.last_review.is_some_and(|last| now.saturating_sub(last) < SECONDS_PER_DAY);
+        if config.algorithm == Algorithm::Fsrs && rating != Rating::Again && same_day {
+            return;
+        }
+        let previous_review = card.last_review.replace(now);
+
+        match config.algorithm {
+            // Answers given before a kanji is due don't move it along
+            Algorithm::FixedIntervals => {
+                if rating != Rating::Again && card.due <= now {
+                    card.reps += match rating {
+                        Rating::Hard => 0,
+                        Rating::Easy => 2,
+                        _ => 1,
+                    };
+                    card.due = now + shorten_for_confusions(config.interval(card.reps.max(1)), confusions);
+                }
+            }
+            Algorithm::Fsrs => {
+                let elapsed_days = previous_review
+                    .map_or(0.0, |previous| now.saturating_sub(previous) as f64 / SECONDS_PER_DAY as f64);
+                let memory = Fsrs::new(&config.fsrs).next_state(card.memory, elapsed_days, rating);
+                card.memory = Some(memory);
+
+                if rating != Rating::Again {
+                    card.reps += 1;
+                    let days = Fsrs::interval_days(
+                        memory.stability,
+                        config.fsrs.target_retention,
+                        config.fsrs.maximum_interval_days,
+                    );
+                    card.due = now + shorten_for_confusions(days * SECONDS_PER_DAY, confusions);
+                }
+            }
+        }
+
+        if rating != Rating::Again {
+            return;
+        }
+
+        if card.reps > 0 {
+            card.lapses += 1;
+            card.last_lapse = Some(now);
+        }
+        card.reps = 0;
+        card.due = now;
+
+        if card.lapses >= config.leech_threshold && !card.leech {
+            card.leech = true;
+            if config.suspend_leeches {
+                card.suspended = true;
+            }
+        }
+    }
+}
+
+// When both are needed, lock the review log first, then the scheduler
+pub struct SchedulerState(Mutex<Scheduler>);
+
+impl SchedulerState {
+    pub fn new(scheduler: Scheduler) -> Self {
+        Self(Mutex::new(scheduler))
+    }
+
+    pub fn lock(&self) -> Result<MutexGuard<'_, Scheduler>, String> {
+        self.0.lock().map_err(|_| "Failed to lock scheduler".to_string())
+    }
+}
+
+pub fn remediation(
+    db: &KanjiDatabase,
+    scraper_config: &ScraperConfig,
+    kanji_id: &KanjiId,
+    card: &CardSchedule
+) -> Option<Remediation> {
+    let detail = scraper_config.resolve_detail(db.detail(kanji_id)?.clone());
+
+    Some(Remediation {
+        kanji_id: detail.link,
+        kanji: detail.kanji,
+        meanings: detail.meanings,
+        mnemonic: detail.mnemonic,
+        breakdown: detail.breakdown,
+        lookalikes: detail.lookalikes,
+        lapses: card.lapses,
+    })
+}
+
+#[tauri::command]
+pub fn get_scheduler_config(scheduler: State<'_, SchedulerState>) -> Result<SchedulerConfig, String> {
+    Ok(scheduler.lock()?.config().clone())
+}
+
+// Change the active profile's settings
+#[tauri::command]
+pub fn update_scheduler_config(
+    review_log: State<'_, ReviewLogState>,
+    scheduler: State<'_, SchedulerState>,
+    config: SchedulerConfig
+) -> Result<(), String> {
+    let review_log = review_log.lock()?;
+    let mut scheduler = scheduler.lock()?;
+    let active = scheduler.profiles.active.clone();
+    scheduler.apply_profile(&review_log, active, config)
+}
+
+#[tauri::command]
+pub fn get_scheduler_profiles(scheduler: State<'_, SchedulerState>) -> Result<SchedulerProfiles, String> {
+    Ok(scheduler.lock()?.profiles.clone())
+}
+
+// Switch to another profile, creating it from the current settings if it doesn't exist yet
+#[tauri::command]
+pub fn select_scheduler_profile(
+    review_log: State<'_, ReviewLogState>,
+    scheduler: State<'_, SchedulerState>,
+    name: String
+) -> Result<SchedulerConfig, String> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err("Pick a name for the profile".to_string());
+    }
+
+    let review_log = review_log.lock()?;
+    let mut scheduler = scheduler.lock()?;
+    let config = scheduler.profiles.profiles
+        .get(&name)
+        .unwrap_or(scheduler.config())
+        .clone();
+    scheduler.apply_profile(&review_log, name, config.clone())?;
+
+    Ok(config)
+}
+
+#[tauri::command]
+pub fn delete_scheduler_profile(scheduler: State<'_, SchedulerState>, name: String) -> Result<(), String> {
+    let mut scheduler = scheduler.lock()?;
+    if scheduler.profiles.active == name {
+        return Err(format!("Switch to another profile before deleting {}", name));
+    }
+    if scheduler.profiles.profiles.remove(&name).is_none() {
+        return Err(format!("Profile {} not found", name));
+    }
+    scheduler.save_profiles()
+}
+
+#[tauri::command]
+pub fn get_leeches(
+    state: State<'_, KanjiDatabaseState>,
+    scraper_config: State<'_, ScraperConfigState>,
+    scheduler: State<'_, SchedulerState>
+) -> Result<Vec<Leech>, String> {
+    let db = state.read()?;
+    let config = scraper_config.read()?;
+    let scheduler = scheduler.lock()?;
+
+    let mut leeches: Vec<Leech> = scheduler.cards
+        .iter()
+        .filter(|(_, card)| card.leech)
+        .map(|(kanji_id, card)| Leech {
+            kanji_id: kanji_id.clone(),
+            kanji: db.detail(kanji_id)
+                .map(|detail| config.resolve(&detail.kanji))
+                .unwrap_or_default(),
+            lapses: card.lapses,
+            suspended: card.suspended,
+            needs_remediation: card.needs_remediation(),
+        })
+        .collect();
+
+    leeches.sort_by_key(|leech| Reverse(leech.lapses));
+    Ok(leeches)
+}
+
+// Leeches that lapsed again since their remediation was last shown, worst first
+#[tauri::command]
+pub fn get_remediation_queue(
+    state: State<'_, KanjiDatabaseState>,
+    scraper_config: State<'_, ScraperConfigState>,
+    scheduler: State<'_, SchedulerState>
+) -> Result<Vec<Remediation>, String> {
+    let db = state.read()?;
+    let config = scraper_config.read()?;
+    let scheduler = scheduler.lock()?;
+
+    let mut queue: Vec<Remediation> = scheduler.cards
+        .iter()
+        .filter(|(_, card)| card.needs_remediation())
+        .filter_map(|(kanji_id, card)| remediation(&db, &config, kanji_id, card))
+        .collect();
+
+    queue.sort_by_key(|item| Reverse(item.lapses));
+    Ok(queue)
+}
+
+#[tauri::command]
+pub fn complete_remediation(
+    scheduler: State<'_, SchedulerState>,
+    history: State<'_, HistoryState>,
+    url: String
+) -> Result<(), String> {
+    let kanji_id = KanjiId::parse(&url).ok_or_else(|| format!("Invalid kanji link: {}", url))?;
+    let mut scheduler = scheduler.lock()?;
+
+    let card = scheduler.cards
+        .get_mut(&kanji_id)
+        .ok_or_else(|| format!("{} has no review history", kanji_id))?;
+    let before = card.remediated_at;
+    card.remediated_at = Some(storage::unix_now());
+    let after = card.remediated_at;
+
+    scheduler.save()?;
+    history.record(format!("Mark kanji #{} as relearned", kanji_id.index), Change::Remediated {
+        kanji_id,
+        before,
+        after,
+    })
+}
+
+#[tauri::command]
+pub fn set_suspended(
+    scheduler: State<'_, SchedulerState>,
+    history: State<'_, HistoryState>,
+    url: String,
+    suspended: bool
+) -> Result<(), String> {
+    let kanji_id = KanjiId::parse(&url).ok_or_else(|| format!("Invalid kanji link: {}", url))?;
+    let mut scheduler = scheduler.lock()?;
+
+    let card = scheduler.cards.entry(kanji_id.clone()).or_default();
+    let before = card.suspended;
+    card.suspended = suspended;
+    scheduler.save()?;
+
+    let description = if suspended {
+        format!("Suspend kanji #{}", kanji_id.index)
+    } else {
+        format!("Unsuspend kanji #{}", kanji_id.index)
+    };
+    history.record(description, Change::Suspended { kanji_id, before, after: suspended })
+}
+
+// Forget a kanji's review progress so it's scheduled as new. The review log is kept.
+#[tauri::command]
+pub fn reset_schedule(
+    scheduler: State<'_, SchedulerState>,
+    history: State<'_, HistoryState>,
+    url: String
+) -> Result<(), String> {
+    let kanji_id = KanjiId::parse(&url).ok_or_else(|| format!("Invalid kanji link: {}", url))?;
+    let mut scheduler = scheduler.lock()?;
+
+    let Some(card) = scheduler.cards.remove(&kanji_id) else {
+        return Ok(());
+    };
+    scheduler.save()?;
+
+    history.record(format!("Reset reviews of kanji #{}", kanji_id.index), Change::Reset { kanji_id, card })
+}
+
+// Fit the FSRS weights to the review log and switch to them. Runs off the main thread since
+// it replays the whole history a few thousand times.
+#[tauri::command]
+pub async fn optimize_fsrs(
+    review_log: State<'_, ReviewLogState>,
+    scheduler: State<'_, SchedulerState>
+) -> Result<OptimizeResult, String> {
+    let entries = review_log.lock()?.entries.clone();
+    // Fitted for the profile active now, even if another one is picked meanwhile
+    let (profile, weights) = {
+        let scheduler = scheduler.lock()?;
+        (scheduler.profiles.active.clone(), scheduler.config().fsrs.weights.clone())
+    };
+
+    let result = tauri::async_runtime::spawn_blocking(move || fsrs::optimize(&weights, &entries))
+        .await
+        .map_err(|e| format!("Failed to optimize FSRS parameters: {}", e))??;
+
+    let mut scheduler = scheduler.lock()?;
+    let Some(config) = scheduler.profiles.profiles.get_mut(&profile) else {
+        return Err(format!("Profile {} was deleted while optimizing", profile));
+    };
+    config.fsrs.weights = result.weights.clone();
+    scheduler.save_profiles()?;
+
+    Ok(result)
+}
@@ -0,0 +1,433 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use super::models::{CardFilter, PracticeCard, PracticeType, Rating, ReviewEntry};
+use super::review_log::ReviewLogState;
+use super::scheduler::{remediation, Remediation, ReviewQueue, SchedulerState};
+use super::{answer_entry, order_by_usefulness, rating_entry, resolve_card, session_cards};
+use crate::dictionary::kanjidic::KanjidicState;
+use crate::dictionary::kanjivg::KanjivgState;
+use crate::parser::config::ScraperConfigState;
+use crate::parser::models::{KanjiDatabaseState, KanjiId};
+use crate::storage;
+use crate::user_data::answers::UserAnswersState;
+use crate::user_data::lists::StudyListsState;
+
+pub const SESSION_PATH: &str = "practice_session.json";
+
+// A missed kanji comes back after this many others, so it isn't just answered from memory
+const REQUEUE_GAP: usize = 3;
+
+// One kanji in the queue, with a question for each practice type asked about it
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SessionItem {
+    pub kanji_id: KanjiId,
+    pub questions: Vec<PracticeCard>,
+    // None until the matching question is answered
+    pub results: Vec<Option<bool>>,
+    // How many times the item has been put back for a wrong answer
+    pub requeued: u32,
+    // Shown before the questions when the kanji is a leech that lapsed again
+    #[serde(default)]
+    pub remediation: Option<Remediation>,
+    // Worst rating given on the first pass, the kanji is reviewed once with it
+    #[serde(default)]
+    pub rating: Option<Rating>,
+}
+
+impl SessionItem {
+    fn new(kanji_id: KanjiId, questions: Vec<PracticeCard>) -> Self {
+        let results = vec![None; questions.len()];
+        Self { kanji_id, questions, results, requeued: 0, remediation: None, rating: None }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.results.iter().all(Option::is_some)
+    }
+
+    fn all_correct(&self) -> bool {
+        self.results.iter().all(|r| *r == Some(true))
+    }
+
+    fn usefulness(&self) -> u8 {
+        self.questions.iter().map(|q| q.usefulness).max().unwrap_or(0)
+    }
+
+    fn asks(&self, card: &PracticeCard) -> bool {
+        self.questions.iter().any(|q| q.practice_type == card.practice_type && q.prompt == card.prompt)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PracticeSession {
+    pub practice_types: Vec<PracticeType>,
+    pub started_at: u64,
+    pub current: Option<SessionItem>,
+    pub queue: VecDeque<SessionItem>,
+    pub total: usize,
+    pub completed: usize,
+    pub answered: u32,
+    pub correct: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SessionProgress {
+    pub practice_types: Vec<PracticeType>,
+    pub started_at: u64,
+    pub total: usize,
+    pub completed: usize,
+    // Items still waiting, including the one being answered
+    pub remaining: usize,
+    pub answered: u32,
+    pub correct: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AnswerResult {
+    pub correct: bool,
+    pub answers: Vec<String>,
+    pub hint: Option<String>,
+    // Every question of the current item has been answered
+    pub item_finished: bool,
+    pub requeued: bool,
+    pub progress: SessionProgress,
+}
+
+impl PracticeSession {
+    // Items are asked in the order given
+    fn new(practice_types: Vec<PracticeType>, items: Vec<SessionItem>) -> Self {
+        let total = items.len();
+
+        Self {
+            practice_types,
+            started_at: storage::unix_now(),
+            current: None,
+            queue: items.into(),
+            total,
+            completed: 0,
+            answered: 0,
+            correct: 0,
+        }
+    }
+
+    pub fn progress(&self) -> SessionProgress {
+        SessionProgress {
+            practice_types: self.practice_types.clone(),
+            started_at: self.started_at,
+            total: self.total,
+            completed: self.completed,
+            remaining: self.queue.len() + usize::from(self.current.is_some()),
+            answered: self.answered,
+            correct: self.correct,
+        }
+    }
+
+    // The item being answered, pulling the next one off the queue if needed
+    pub fn next_item(&mut self) -> Option<&SessionItem> {
+        if self.current.is_none() {
+            self.current = self.queue.pop_front();
+        }
+        self.current.as_ref()
+    }
+
+    // Move on once every question of the current item is answered: a clean item is done,
+    // anything missed goes back into the queue with its results cleared
+    fn finish_item(&mut self) -> bool {
+        let Some(mut item) = self.current.take() else {
+            return false;
+        };
+
+        if item.all_correct() {
+            self.completed += 1;
+            return false;
+        }
+
+        item.results.fill(None);
+        item.requeued += 1;
+        let position = REQUEUE_GAP.min(self.queue.len());
+        self.queue.insert(position, item);
+        true
+    }
+}
+
+// Group each kanji's cards into one queue item, keeping kanji order stable. Repeats of a
+// question, like lookalike cards for a kanji the user keeps confusing, get items of their own
+// so they're asked apart instead of back to back.
+fn session_items(cards: Vec<PracticeCard>) -> Vec<SessionItem> {
+    let mut grouped: BTreeMap<KanjiId, Vec<SessionItem>> = BTreeMap::new();
+    for card in cards {
+        let items = grouped.entry(card.kanji_id.clone()).or_default();
+        match items.iter_mut().find(|item| !item.asks(&card)) {
+            Some(item) => {
+                item.questions.push(card);
+                item.results.push(None);
+            }
+            None => items.push(SessionItem::new(card.kanji_id.clone(), vec![card])),
+        }
+    }
+
+    grouped.into_values().flatten().collect()
+}
+
+// The session in progress, mirrored to disk after every change so it survives a restart
+pub struct SessionState(Mutex<Option<PracticeSession>>);
+
+impl SessionState {
+    pub fn load() -> Result<Self, String> {
+        Ok(Self(Mutex::new(storage::read_json(SESSION_PATH)?)))
+    }
+
+    pub fn lock(&self) -> Result<MutexGuard<'_, Option<PracticeSession>>, String> {
+        self.0.lock().map_err(|_| "Failed to lock practice session".to_string())
+    }
+}
+
+fn save_session(session: &Option<PracticeSession>) -> Result<(), String> {
+    match session {
+        Some(session) => storage::write_json(SESSION_PATH, session),
+        None if Path::new(SESSION_PATH).exists() => fs::remove_file(SESSION_PATH)
+            .map_err(|e| format!("Failed to remove file {}: {}", SESSION_PATH, e)),
+        None => Ok(()),
+    }
+}
+
+// Start a new session over every practiced kanji, or the kanji in the filter's study lists,
+// replacing any unfinished one
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub fn start_session(
+    state: State<'_, KanjiDatabaseState>,
+    scraper_config: State<'_, ScraperConfigState>,
+    review_log: State<'_, ReviewLogState>,
+    scheduler: State<'_, SchedulerState>,
+    lists: State<'_, StudyListsState>,
+    kanjidic: State<'_, KanjidicState>,
+    kanjivg: State<'_, KanjivgState>,
+    session_state: State<'_, SessionState>,
+    practice_types: Vec<PracticeType>,
+    filter: Option<CardFilter>
+) -> Result<SessionProgress, String> {
+    if practice_types.is_empty() {
+        return Err("Pick at least one practice type".to_string());
+    }
+    let filter = filter.unwrap_or_default();
+
+    let items = {
+        let db = state.read()?;
+        let config = scraper_config.read()?;
+        let review_log = review_log.lock()?;
+        let scheduler = scheduler.lock()?;
+        let lists = lists.read()?;
+        let metadata = kanjidic.read()?;
+        let strokes = kanjivg.read()?;
+
+        let now = storage::unix_now();
+        let cards = practice_types
+            .iter()
+            .flat_map(|practice_type| {
+                session_cards(&db, &review_log, &scheduler, &lists, &metadata, &strokes, *practice_type, &filter, now)
+            })
+            .collect();
+
+        // Whole items are ordered and capped, so a kanji keeps every practice type asked about it
+        let mut items = session_items(cards);
+        order_by_usefulness(
+            &mut items,
+            |item| scheduler.queue(&item.kanji_id, now).unwrap_or(ReviewQueue::New),
+            SessionItem::usefulness,
+            &filter,
+            &mut rand::thread_rng(),
+        );
+
+        // A kanji with repeated questions only needs its remediation shown once
+        let mut remediated = HashSet::new();
+        for item in &mut items {
+            if !remediated.insert(item.kanji_id.clone()) {
+                continue;
+            }
+            item.remediation = scheduler.cards
+                .get(&item.kanji_id)
+                .filter(|card| card.needs_remediation())
+                .and_then(|card| remediation(&db, &config, &item.kanji_id, card));
+        }
+        items
+    };
+
+    let session = PracticeSession::new(practice_types, items);
+    let progress = session.progress();
+
+    let mut current = session_state.lock()?;
+    *current = Some(session);
+    save_session(&current)?;
+
+    Ok(progress)
+}
+
+// The unfinished session left over from last time, if any
+#[tauri::command]
+pub fn get_session(session_state: State<'_, SessionState>) -> Result<Option<SessionProgress>, String> {
+    Ok(session_state.lock()?.as_ref().map(PracticeSession::progress))
+}
+
+// The item to answer next, or None once the queue is empty
+#[tauri::command]
+pub fn next_card(
+    scraper_config: State<'_, ScraperConfigState>,
+    scheduler: State<'_, SchedulerState>,
+    session_state: State<'_, SessionState>
+) -> Result<Option<SessionItem>, String> {
+    let config = scraper_config.read()?;
+    let mut session = session_state.lock()?;
+    let Some(active) = session.as_mut() else {
+        return Err("No practice session in progress".to_string());
+    };
+
+    let had_current = active.current.is_some();
+    let mut item = active.next_item().cloned();
+    if !had_current && item.is_some() {
+        save_session(&session)?;
+    }
+
+    // Handing out the remediation counts as showing it
+    if let Some(remediation) = item.as_ref().and_then(|item| item.remediation.as_ref()) {
+        let mut scheduler = scheduler.lock()?;
+        if let Some(card) = scheduler.cards.get_mut(&remediation.kanji_id) {
+            card.remediated_at = Some(storage::unix_now());
+            scheduler.save()?;
+        }
+    }
+
+    if let Some(item) = &mut item {
+        item.questions = item.questions
+            .drain(..)
+            .map(|card| resolve_card(card, &config))
+            .collect();
+    }
+    Ok(item)
+}
+
+// Answer one question of the current item, `grade` turns it into a review log entry. The
+// scheduler only hears about the item once its first pass is over, graded by its worst
+// answer, so neither the other questions about the kanji nor a requeue count as more reviews.
+fn answer_question(
+    scraper_config: &ScraperConfigState,
+    review_log: &ReviewLogState,
+    scheduler: &SchedulerState,
+    session_state: &SessionState,
+    question: usize,
+    grade: impl FnOnce(&PracticeCard) -> Result<ReviewEntry, String>
+) -> Result<AnswerResult, String> {
+    let config = scraper_config.read()?;
+    let mut session = session_state.lock()?;
+    let Some(active) = session.as_mut() else {
+        return Err("No practice session in progress".to_string());
+    };
+    let Some(item) = active.current.as_mut() else {
+        return Err("No card to answer, call next_card first".to_string());
+    };
+
+    let card = item.questions
+        .get(question)
+        .cloned()
+        .ok_or_else(|| format!("Question {} not found", question))?;
+    if item.results[question].is_some() {
+        return Err(format!("Question {} was already answered", question));
+    }
+
+    // Choice texts are handed out resolved, so grade against the resolved card
+    let card = resolve_card(card, &config);
+    let entry = grade(&card)?;
+    let correct = entry.correct;
+
+    item.results[question] = Some(correct);
+    let item_finished = item.is_finished();
+    let first_pass = item.requeued == 0;
+    if first_pass {
+        let rating = entry.rating();
+        item.rating = Some(item.rating.map_or(rating, |worst| worst.min(rating)));
+    }
+    let review = item.rating
+        .filter(|_| first_pass && item_finished)
+        .map(|rating| (item.kanji_id.clone(), rating));
+
+    active.answered += 1;
+    if correct {
+        active.correct += 1;
+    }
+    let requeued = item_finished && active.finish_item();
+    let progress = active.progress();
+
+    save_session(&session)?;
+
+    let answered_at = entry.answered_at;
+    let confused_with = entry.confused_with.clone();
+    let mut review_log = review_log.lock()?;
+    review_log.record(entry)?;
+
+    if review.is_some() || confused_with.is_some() {
+        let mut scheduler = scheduler.lock()?;
+        if let Some((kanji_id, rating)) = review {
+            scheduler.review(&kanji_id, rating, answered_at, review_log.confusions_of(&kanji_id));
+        }
+        if let Some(other) = confused_with {
+            scheduler.bring_forward(&other, answered_at);
+        }
+        scheduler.save()?;
+    }
+
+    Ok(AnswerResult {
+        correct,
+        answers: card.answers,
+        hint: card.hint,
+        item_finished,
+        requeued,
+        progress,
+    })
+}
+
+// Grade a typed or picked answer to one question of the current item
+#[tauri::command]
+pub fn submit_answer(
+    scraper_config: State<'_, ScraperConfigState>,
+    review_log: State<'_, ReviewLogState>,
+    scheduler: State<'_, SchedulerState>,
+    user_answers: State<'_, UserAnswersState>,
+    session_state: State<'_, SessionState>,
+    question: usize,
+    answer: String
+) -> Result<AnswerResult, String> {
+    answer_question(&scraper_config, &review_log, &scheduler, &session_state, question, |card| {
+        answer_entry(&user_answers, card, &answer)
+    })
+}
+
+// Self-rate one question of the current item after revealing its answer
+#[tauri::command]
+pub fn rate_answer(
+    scraper_config: State<'_, ScraperConfigState>,
+    review_log: State<'_, ReviewLogState>,
+    scheduler: State<'_, SchedulerState>,
+    session_state: State<'_, SessionState>,
+    question: usize,
+    rating: Rating
+) -> Result<AnswerResult, String> {
+    answer_question(&scraper_config, &review_log, &scheduler, &session_state, question, |card| {
+        Ok(rating_entry(card, rating))
+    })
+}
+
+#[tauri::command]
+pub fn end_session(session_state: State<'_, SessionState>) -> Result<Option<SessionProgress>, String> {
+    let mut session = session_state.lock()?;
+    let progress = session.as_ref().map(PracticeSession::progress);
+
+    *session = None;
+    save_session(&session)?;
+
+    Ok(progress)
+}
@@ -0,0 +1,80 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+// Read a JSON file, returning None if it doesn't exist yet
+pub fn read_json<T: DeserializeOwned>(file_path: &str) -> Result<Option<T>, String> {
+    if !Path::new(file_path).exists() {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(file_path)
+        .map_err(|e| format!("Failed to read file {}: {}", file_path, e))?;
+    let value = serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse JSON in {}: {}", file_path, e))?;
+
+    Ok(Some(value))
+}
+
+// Write pretty JSON through a temp file so a crash mid-write can't truncate the original
+pub fn write_json<T: Serialize + ?Sized>(file_path: &str, value: &T) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(value)
+        .map_err(|e| format!("Failed to serialize JSON for {}: {}", file_path, e))?;
+
+    write_atomic(file_path, &json)
+}
+
+pub fn write_atomic(file_path: &str, contents: &str) -> Result<(), String> {
+    let tmp_path = format!("{}.tmp", file_path);
+    fs::write(&tmp_path, contents)
+        .map_err(|e| format!("Failed to write file {}: {}", tmp_path, e))?;
+    fs::rename(&tmp_path, file_path)
+        .map_err(|e| format!("Failed to replace file {}: {}", file_path, e))?;
+
+    Ok(())
+}
+
+pub const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Append-only logs are stored one JSON record per line so recording doesn't rewrite the file
+pub fn read_json_lines<T: DeserializeOwned>(file_path: &str) -> Result<Vec<T>, String> {
+    if !Path::new(file_path).exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(file_path)
+        .map_err(|e| format!("Failed to read file {}: {}", file_path, e))?;
+
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line)
+                .map_err(|e| format!("Failed to parse JSON in {}: {}", file_path, e))
+        })
+        .collect()
+}
+
+pub fn append_json_line<T: Serialize>(file_path: &str, value: &T) -> Result<(), String> {
+    let mut line = serde_json::to_string(value)
+        .map_err(|e| format!("Failed to serialize JSON for {}: {}", file_path, e))?;
+    line.push('\n');
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file_path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .map_err(|e| format!("Failed to write file {}: {}", file_path, e))
+}
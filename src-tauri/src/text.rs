@@ -0,0 +1,30 @@
+// KanjiDamage writes jukugo as "一緒(いっしょ)", drop the trailing reading
+pub fn strip_reading_suffix(text: &str) -> &str {
+    let text = text.trim();
+
+    for (open, close) in [('(', ')'), ('（', '）')] {
+        if text.ends_with(close) {
+            if let Some(start) = text.rfind(open) {
+                return text[..start].trim_end();
+            }
+        }
+    }
+
+    text
+}
+
+fn katakana_to_hiragana(c: char) -> char {
+    match c {
+        'ァ'..='ヶ' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+        _ => c,
+    }
+}
+
+// Fold katakana into hiragana and drop spacing and okurigana markers so kana answers compare equal
+pub fn normalize_kana(text: &str) -> String {
+    strip_reading_suffix(text)
+        .chars()
+        .filter(|c| !c.is_whitespace() && !matches!(c, '・' | '.' | '-' | '*' | '。' | '、'))
+        .map(katakana_to_hiragana)
+        .collect()
+}
@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use super::history::{Change, HistoryState};
+use crate::parser::models::KanjiId;
+use crate::practice::models::PracticeType;
+use crate::storage;
+
+pub const USER_ANSWERS_PATH: &str = "user_answers.json";
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct UserAnswer {
+    pub practice_type: PracticeType,
+    pub text: String,
+    // The jukugo the answer is for, since a kanji's page lists several. None for the kanji's own cards.
+    #[serde(default)]
+    pub word: Option<String>,
+}
+
+// The user's own additions to a kanji's answers, kept apart from the scraped page
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct KanjiAnswers {
+    // Graded correct on top of the scraped answers
+    pub accepted: Vec<UserAnswer>,
+    // Graded wrong even when they match a scraped answer
+    pub blocked: Vec<UserAnswer>,
+}
+
+impl KanjiAnswers {
+    pub fn is_empty(&self) -> bool {
+        self.accepted.is_empty() && self.blocked.is_empty()
+    }
+}
+
+pub type UserAnswers = BTreeMap<KanjiId, KanjiAnswers>;
+
+pub fn load() -> Result<UserAnswers, String> {
+    Ok(storage::read_json(USER_ANSWERS_PATH)?.unwrap_or_default())
+}
+
+pub struct UserAnswersState(RwLock<UserAnswers>);
+
+impl UserAnswersState {
+    pub fn new(answers: UserAnswers) -> Self {
+        Self(RwLock::new(answers))
+    }
+
+    pub fn read(&self) -> Result<RwLockReadGuard<'_, UserAnswers>, String> {
+        self.0.read().map_err(|_| "Failed to lock user answers".to_string())
+    }
+
+    pub fn write(&self) -> Result<RwLockWriteGuard<'_, UserAnswers>, String> {
+        self.0.write().map_err(|_| "Failed to lock user answers".to_string())
+    }
+}
+
+#[tauri::command]
+pub fn get_user_answers(user_answers: State<'_, UserAnswersState>, url: String) -> Result<KanjiAnswers, String> {
+    let kanji_id = KanjiId::parse(&url).ok_or_else(|| format!("Invalid kanji link: {}", url))?;
+    Ok(user_answers.read()?.get(&kanji_id).cloned().unwrap_or_default())
+}
+
+// Replace a kanji's accepted and blocked answers
+#[tauri::command]
+pub fn set_user_answers(
+    user_answers: State<'_, UserAnswersState>,
+    history: State<'_, HistoryState>,
+    url: String,
+    answers: KanjiAnswers
+) -> Result<(), String> {
+    let kanji_id = KanjiId::parse(&url).ok_or_else(|| format!("Invalid kanji link: {}", url))?;
+    let mut user_answers = user_answers.write()?;
+
+    let after = (!answers.is_empty()).then_some(answers);
+    let before = match &after {
+        Some(answers) => user_answers.insert(kanji_id.clone(), answers.clone()),
+        None => user_answers.remove(&kanji_id),
+    };
+
+    storage::write_json(USER_ANSWERS_PATH, &*user_answers)?;
+    let description = format!("Edit answers for kanji #{}", kanji_id.index);
+    history.record(description, Change::Answers { kanji_id, before, after })
+}
@@ -0,0 +1,250 @@
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard};
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use super::answers::{KanjiAnswers, UserAnswersState, USER_ANSWERS_PATH};
+use super::lists::{StudyLists, StudyListsState};
+use super::notes::{KanjiNotes, NotesState, KANJI_NOTES_PATH};
+use crate::parser::flusher::FlushTarget;
+use crate::parser::models::{KanjiDatabaseState, KanjiId};
+use crate::practice::scheduler::{CardSchedule, SchedulerState};
+use crate::storage;
+
+// Older changes fall off the end once the history is this long
+const MAX_HISTORY: usize = 100;
+
+// A change to user state, holding both sides so it can be undone and redone.
+// None means there was no entry before or after.
+#[derive(Debug, Clone)]
+pub enum Change {
+    // Kanji whose practice flag was set to `practice`; kanji already in that state are left out
+    Practice { indexes: Vec<u32>, practice: bool },
+    Notes { kanji_id: KanjiId, before: Option<KanjiNotes>, after: Option<KanjiNotes> },
+    Answers { kanji_id: KanjiId, before: Option<KanjiAnswers>, after: Option<KanjiAnswers> },
+    // Lists are small enough to keep whole copies
+    Lists { before: StudyLists, after: StudyLists },
+    // Only the touched field is kept for schedule changes, so undoing one doesn't lose the
+    // reviews made since
+    Suspended { kanji_id: KanjiId, before: bool, after: bool },
+    Remediated { kanji_id: KanjiId, before: Option<u64>, after: Option<u64> },
+    // The progress a reset threw away
+    Reset { kanji_id: KanjiId, card: CardSchedule },
+}
+
+struct HistoryEntry {
+    id: u64,
+    description: String,
+    recorded_at: u64,
+    change: Change,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HistoryItem {
+    pub id: u64,
+    pub description: String,
+    pub recorded_at: u64,
+}
+
+// Both stacks, most recent first
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HistoryListing {
+    pub undo: Vec<HistoryItem>,
+    pub redo: Vec<HistoryItem>,
+}
+
+impl HistoryEntry {
+    fn item(&self) -> HistoryItem {
+        HistoryItem {
+            id: self.id,
+            description: self.description.clone(),
+            recorded_at: self.recorded_at,
+        }
+    }
+}
+
+#[derive(Default)]
+struct History {
+    undo: VecDeque<HistoryEntry>,
+    redo: Vec<HistoryEntry>,
+    next_id: u64,
+}
+
+// Kept in memory only, so every launch starts with an empty history
+pub struct HistoryState(Mutex<History>);
+
+impl HistoryState {
+    pub fn new() -> Self {
+        Self(Mutex::new(History::default()))
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, History>, String> {
+        self.0.lock().map_err(|_| "Failed to lock undo history".to_string())
+    }
+
+    // A new change makes anything that was undone impossible to redo
+    pub fn record(&self, description: impl Into<String>, change: Change) -> Result<(), String> {
+        let mut history = self.lock()?;
+        let id = history.next_id;
+        history.next_id += 1;
+
+        history.redo.clear();
+        history.undo.push_back(HistoryEntry {
+            id,
+            description: description.into(),
+            recorded_at: storage::unix_now(),
+            change,
+        });
+        if history.undo.len() > MAX_HISTORY {
+            history.undo.pop_front();
+        }
+        Ok(())
+    }
+
+    // For when the state is replaced wholesale and the recorded sides no longer line up
+    pub fn clear(&self) -> Result<(), String> {
+        let mut history = self.lock()?;
+        history.undo.clear();
+        history.redo.clear();
+        Ok(())
+    }
+}
+
+fn side<T: Clone>(before: &Option<T>, after: &Option<T>, undo: bool) -> Option<T> {
+    if undo { before.clone() } else { after.clone() }
+}
+
+// Put the state back to one side of `change`: `before` when undoing, `after` when redoing
+fn apply(
+    change: &Change,
+    undo: bool,
+    state: &KanjiDatabaseState,
+    notes: &NotesState,
+    user_answers: &UserAnswersState,
+    lists: &StudyListsState,
+    scheduler: &SchedulerState
+) -> Result<(), String> {
+    match change {
+        Change::Practice { indexes, practice } => {
+            {
+                let mut db = state.write()?;
+                for index in indexes {
+                    db.set_practice(*index, *practice != undo)?;
+                }
+            }
+            state.mark_dirty(&[FlushTarget::Listings, FlushTarget::Details]);
+        }
+        Change::Notes { kanji_id, before, after } => {
+            let mut notes = notes.write()?;
+            match side(before, after, undo) {
+                Some(kanji_notes) => notes.insert(kanji_id.clone(), kanji_notes),
+                None => notes.remove(kanji_id),
+            };
+            storage::write_json(KANJI_NOTES_PATH, &*notes)?;
+        }
+        Change::Answers { kanji_id, before, after } => {
+            let mut user_answers = user_answers.write()?;
+            match side(before, after, undo) {
+                Some(answers) => user_answers.insert(kanji_id.clone(), answers),
+                None => user_answers.remove(kanji_id),
+            };
+            storage::write_json(USER_ANSWERS_PATH, &*user_answers)?;
+        }
+        Change::Lists { before, after } => {
+            let mut lists = lists.write()?;
+            *lists = if undo { before.clone() } else { after.clone() };
+            lists.save()?;
+        }
+        Change::Suspended { kanji_id, before, after } => {
+            let mut scheduler = scheduler.lock()?;
+            scheduler.cards.entry(kanji_id.clone()).or_default().suspended = if undo { *before } else { *after };
+            scheduler.save()?;
+        }
+        Change::Remediated { kanji_id, before, after } => {
+            let mut scheduler = scheduler.lock()?;
+            // Nothing to put back if the card was reset since
+            if let Some(card) = scheduler.cards.get_mut(kanji_id) {
+                card.remediated_at = if undo { *before } else { *after };
+                scheduler.save()?;
+            }
+        }
+        Change::Reset { kanji_id, card } => {
+            let mut scheduler = scheduler.lock()?;
+            if undo {
+                scheduler.cards.insert(kanji_id.clone(), card.clone());
+            } else {
+                scheduler.cards.remove(kanji_id);
+            }
+            scheduler.save()?;
+        }
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_history(history: State<'_, HistoryState>) -> Result<HistoryListing, String> {
+    let history = history.lock()?;
+    Ok(HistoryListing {
+        undo: history.undo.iter().rev().map(HistoryEntry::item).collect(),
+        redo: history.redo.iter().rev().map(HistoryEntry::item).collect(),
+    })
+}
+
+// Revert the most recent change, returning what was reverted
+#[tauri::command]
+pub fn undo(
+    state: State<'_, KanjiDatabaseState>,
+    notes: State<'_, NotesState>,
+    user_answers: State<'_, UserAnswersState>,
+    lists: State<'_, StudyListsState>,
+    scheduler: State<'_, SchedulerState>,
+    history: State<'_, HistoryState>
+) -> Result<Option<HistoryItem>, String> {
+    // Not held while applying, the commands that record changes take it with their own locks held
+    let Some(entry) = history.lock()?.undo.pop_back() else {
+        return Ok(None);
+    };
+
+    // Reviews aren't recorded here, so a reset of a kanji reviewed since can't be undone without
+    // losing them. The entry is dropped, it would block every undo behind it.
+    if let Change::Reset { kanji_id, .. } = &entry.change {
+        if scheduler.lock()?.cards.get(kanji_id).is_some_and(|card| card.last_review.is_some()) {
+            return Err(format!("Kanji #{} was reviewed after being reset, the reset can't be undone", kanji_id.index));
+        }
+    }
+
+    if let Err(e) = apply(&entry.change, true, &state, &notes, &user_answers, &lists, &scheduler) {
+        history.lock()?.undo.push_back(entry);
+        return Err(e);
+    }
+
+    let item = entry.item();
+    history.lock()?.redo.push(entry);
+    Ok(Some(item))
+}
+
+// Reapply the most recently undone change
+#[tauri::command]
+pub fn redo(
+    state: State<'_, KanjiDatabaseState>,
+    notes: State<'_, NotesState>,
+    user_answers: State<'_, UserAnswersState>,
+    lists: State<'_, StudyListsState>,
+    scheduler: State<'_, SchedulerState>,
+    history: State<'_, HistoryState>
+) -> Result<Option<HistoryItem>, String> {
+    let Some(entry) = history.lock()?.redo.pop() else {
+        return Ok(None);
+    };
+
+    if let Err(e) = apply(&entry.change, false, &state, &notes, &user_answers, &lists, &scheduler) {
+        history.lock()?.redo.push(entry);
+        return Err(e);
+    }
+
+    let item = entry.item();
+    history.lock()?.undo.push_back(entry);
+    Ok(Some(item))
+}
@@ -0,0 +1,285 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use super::history::{Change, HistoryState};
+use crate::parser::config::ScraperConfigState;
+use crate::parser::models::{KanjiDatabaseState, KanjiDetail, KanjiId, KanjiListing};
+use crate::storage;
+
+pub const STUDY_LISTS_PATH: &str = "study_lists.json";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StudyList {
+    pub id: u64,
+    pub name: String,
+    pub created_at: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StudyListSummary {
+    pub id: u64,
+    pub name: String,
+    pub created_at: u64,
+    pub kanji_count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct StudyLists {
+    pub lists: BTreeMap<u64, StudyList>,
+    // Kanji -> the lists it's in
+    pub membership: BTreeMap<KanjiId, BTreeSet<u64>>,
+    pub next_id: u64,
+}
+
+impl StudyLists {
+    pub fn load() -> Result<Self, String> {
+        Ok(storage::read_json(STUDY_LISTS_PATH)?.unwrap_or_default())
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::write_json(STUDY_LISTS_PATH, self)
+    }
+
+    fn list(&self, id: u64) -> Result<&StudyList, String> {
+        self.lists.get(&id).ok_or_else(|| format!("Study list {} not found", id))
+    }
+
+    // Indexes of the kanji in any of `ids`, or None when no lists were asked for
+    pub fn members(&self, ids: &[u64]) -> Option<HashSet<u32>> {
+        if ids.is_empty() {
+            return None;
+        }
+
+        Some(self.membership
+            .iter()
+            .filter(|(_, lists)| ids.iter().any(|id| lists.contains(id)))
+            .map(|(kanji_id, _)| kanji_id.index)
+            .collect())
+    }
+
+    fn summary(&self, list: &StudyList) -> StudyListSummary {
+        StudyListSummary {
+            id: list.id,
+            name: list.name.clone(),
+            created_at: list.created_at,
+            kanji_count: self.membership.values().filter(|lists| lists.contains(&list.id)).count(),
+        }
+    }
+}
+
+pub struct StudyListsState(RwLock<StudyLists>);
+
+impl StudyListsState {
+    pub fn new(lists: StudyLists) -> Self {
+        Self(RwLock::new(lists))
+    }
+
+    pub fn read(&self) -> Result<RwLockReadGuard<'_, StudyLists>, String> {
+        self.0.read().map_err(|_| "Failed to lock study lists".to_string())
+    }
+
+    pub fn write(&self) -> Result<RwLockWriteGuard<'_, StudyLists>, String> {
+        self.0.write().map_err(|_| "Failed to lock study lists".to_string())
+    }
+}
+
+fn parse_links(urls: &[String]) -> Result<Vec<KanjiId>, String> {
+    urls.iter()
+        .map(|url| KanjiId::parse(url).ok_or_else(|| format!("Invalid kanji link: {}", url)))
+        .collect()
+}
+
+// Save the lists and record the edit against the copy taken before it
+fn save_change(
+    history: &HistoryState,
+    description: String,
+    before: StudyLists,
+    after: &StudyLists
+) -> Result<(), String> {
+    after.save()?;
+    history.record(description, Change::Lists { before, after: after.clone() })
+}
+
+fn validate_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("List name can't be empty".to_string());
+    }
+    Ok(name.to_string())
+}
+
+#[tauri::command]
+pub fn get_study_lists(lists: State<'_, StudyListsState>) -> Result<Vec<StudyListSummary>, String> {
+    let lists = lists.read()?;
+    Ok(lists.lists.values().map(|list| lists.summary(list)).collect())
+}
+
+#[tauri::command]
+pub fn create_study_list(
+    lists: State<'_, StudyListsState>,
+    history: State<'_, HistoryState>,
+    name: String
+) -> Result<StudyListSummary, String> {
+    let name = validate_name(&name)?;
+    let mut lists = lists.write()?;
+    let before = lists.clone();
+
+    let id = lists.next_id.max(lists.lists.keys().next_back().map_or(0, |id| id + 1));
+    let list = StudyList {
+        id,
+        name,
+        created_at: storage::unix_now(),
+    };
+    lists.next_id = id + 1;
+    lists.lists.insert(id, list.clone());
+    save_change(&history, format!("Create list \"{}\"", list.name), before, &lists)?;
+
+    Ok(lists.summary(&list))
+}
+
+#[tauri::command]
+pub fn rename_study_list(
+    lists: State<'_, StudyListsState>,
+    history: State<'_, HistoryState>,
+    id: u64,
+    name: String
+) -> Result<(), String> {
+    let name = validate_name(&name)?;
+    let mut lists = lists.write()?;
+    let before = lists.clone();
+
+    let list = lists.lists
+        .get_mut(&id)
+        .ok_or_else(|| format!("Study list {} not found", id))?;
+    let description = format!("Rename list \"{}\" to \"{}\"", list.name, name);
+    list.name = name;
+    save_change(&history, description, before, &lists)
+}
+
+#[tauri::command]
+pub fn delete_study_list(
+    lists: State<'_, StudyListsState>,
+    history: State<'_, HistoryState>,
+    id: u64
+) -> Result<(), String> {
+    let mut lists = lists.write()?;
+    let description = format!("Delete list \"{}\"", lists.list(id)?.name);
+    let before = lists.clone();
+
+    lists.lists.remove(&id);
+    for member_of in lists.membership.values_mut() {
+        member_of.remove(&id);
+    }
+    lists.membership.retain(|_, member_of| !member_of.is_empty());
+    save_change(&history, description, before, &lists)
+}
+
+#[tauri::command]
+pub fn add_to_study_list(
+    lists: State<'_, StudyListsState>,
+    history: State<'_, HistoryState>,
+    id: u64,
+    urls: Vec<String>
+) -> Result<(), String> {
+    let kanji_ids = parse_links(&urls)?;
+    let mut lists = lists.write()?;
+    let description = format!("Add {} kanji to \"{}\"", kanji_ids.len(), lists.list(id)?.name);
+    let before = lists.clone();
+
+    for kanji_id in kanji_ids {
+        lists.membership.entry(kanji_id).or_default().insert(id);
+    }
+    save_change(&history, description, before, &lists)
+}
+
+#[tauri::command]
+pub fn remove_from_study_list(
+    lists: State<'_, StudyListsState>,
+    history: State<'_, HistoryState>,
+    id: u64,
+    urls: Vec<String>
+) -> Result<(), String> {
+    let kanji_ids = parse_links(&urls)?;
+    let mut lists = lists.write()?;
+    let description = format!("Remove {} kanji from \"{}\"", kanji_ids.len(), lists.list(id)?.name);
+    let before = lists.clone();
+
+    for kanji_id in kanji_ids {
+        if let Some(member_of) = lists.membership.get_mut(&kanji_id) {
+            member_of.remove(&id);
+            if member_of.is_empty() {
+                lists.membership.remove(&kanji_id);
+            }
+        }
+    }
+    save_change(&history, description, before, &lists)
+}
+
+// The kanji in a list, in KanjiDamage order
+#[tauri::command]
+pub fn get_study_list_kanji(
+    state: State<'_, KanjiDatabaseState>,
+    scraper_config: State<'_, ScraperConfigState>,
+    lists: State<'_, StudyListsState>,
+    id: u64
+) -> Result<Vec<KanjiListing>, String> {
+    let lists = lists.read()?;
+    lists.list(id)?;
+    let members = lists.members(&[id]).unwrap_or_default();
+
+    let db = state.read()?;
+    let config = scraper_config.read()?;
+    Ok(db.kanjis
+        .iter()
+        .filter(|k| members.contains(&(k.index as u32)))
+        .cloned()
+        .map(|k| config.resolve_listing(k))
+        .collect())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StudyListExport {
+    pub lists: Vec<StudyList>,
+    // Cached pages of every kanji in the lists; kanji that were never opened are left out
+    pub kanjis: Vec<KanjiDetail>,
+    pub exported_at: u64,
+}
+
+// Write the given lists and their kanji to a standalone file, e.g. to share a deck
+#[tauri::command]
+pub fn export_study_lists(
+    state: State<'_, KanjiDatabaseState>,
+    scraper_config: State<'_, ScraperConfigState>,
+    lists: State<'_, StudyListsState>,
+    ids: Vec<u64>,
+    path: String
+) -> Result<usize, String> {
+    let lists = lists.read()?;
+    let exported: Vec<StudyList> = ids
+        .iter()
+        .map(|id| lists.list(*id).cloned())
+        .collect::<Result<_, String>>()?;
+    let members = lists.members(&ids).unwrap_or_default();
+
+    let db = state.read()?;
+    let config = scraper_config.read()?;
+    let kanjis: Vec<KanjiDetail> = db.details
+        .values()
+        .filter(|d| members.contains(&d.index))
+        .cloned()
+        .map(|d| config.resolve_detail(d))
+        .collect();
+
+    let count = kanjis.len();
+    storage::write_json(&path, &StudyListExport {
+        lists: exported,
+        kanjis,
+        exported_at: storage::unix_now(),
+    })?;
+
+    Ok(count)
+}
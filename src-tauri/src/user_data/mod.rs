@@ -0,0 +1,76 @@
+pub mod answers;
+pub mod history;
+pub mod lists;
+pub mod notes;
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::storage;
+use answers::{UserAnswers, UserAnswersState, USER_ANSWERS_PATH};
+use history::HistoryState;
+use lists::{StudyLists, StudyListsState};
+use notes::{Notes, NotesState, KANJI_NOTES_PATH};
+
+// Bumped when the export layout changes in a way older builds can't read
+const EXPORT_VERSION: u32 = 1;
+
+// Everything the user wrote themselves, as opposed to what was scraped
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct UserDataExport {
+    pub version: u32,
+    pub exported_at: u64,
+    pub answers: UserAnswers,
+    pub notes: Notes,
+    pub lists: StudyLists,
+}
+
+#[tauri::command]
+pub fn export_user_data(
+    user_answers: State<'_, UserAnswersState>,
+    notes: State<'_, NotesState>,
+    lists: State<'_, StudyListsState>,
+    path: String
+) -> Result<(), String> {
+    let export = UserDataExport {
+        version: EXPORT_VERSION,
+        exported_at: storage::unix_now(),
+        answers: user_answers.read()?.clone(),
+        notes: notes.read()?.clone(),
+        lists: lists.read()?.clone(),
+    };
+
+    storage::write_json(&path, &export)
+}
+
+// Replace the current user data with a previous export. Changes made before the import
+// can't be undone afterwards.
+#[tauri::command]
+pub fn import_user_data(
+    user_answers: State<'_, UserAnswersState>,
+    notes: State<'_, NotesState>,
+    lists: State<'_, StudyListsState>,
+    history: State<'_, HistoryState>,
+    path: String
+) -> Result<(), String> {
+    let export: UserDataExport = storage::read_json(&path)?
+        .ok_or_else(|| format!("File not found: {}", path))?;
+    if export.version > EXPORT_VERSION {
+        return Err(format!("{} was exported by a newer version of the app", path));
+    }
+
+    let mut answers = user_answers.write()?;
+    storage::write_json(USER_ANSWERS_PATH, &export.answers)?;
+    *answers = export.answers;
+
+    let mut notes = notes.write()?;
+    storage::write_json(KANJI_NOTES_PATH, &export.notes)?;
+    *notes = export.notes;
+
+    let mut lists = lists.write()?;
+    export.lists.save()?;
+    *lists = export.lists;
+
+    history.clear()
+}
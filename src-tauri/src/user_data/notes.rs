@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use super::history::{Change, HistoryState};
+use crate::parser::models::KanjiId;
+use crate::storage;
+
+// Stored apart from the scraped details so refreshing a page can never overwrite them
+pub const KANJI_NOTES_PATH: &str = "kanji_notes.json";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct KanjiNotes {
+    // Shown instead of KanjiDamage's mnemonic
+    pub mnemonic: Option<String>,
+    pub notes: String,
+    pub tags: Vec<String>,
+    pub updated_at: u64,
+}
+
+impl KanjiNotes {
+    pub fn is_empty(&self) -> bool {
+        self.mnemonic.as_deref().is_none_or(|m| m.trim().is_empty())
+            && self.notes.trim().is_empty()
+            && self.tags.is_empty()
+    }
+
+    // Case-insensitive substring match over everything the user wrote
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return false;
+        }
+
+        self.mnemonic.iter()
+            .chain(std::iter::once(&self.notes))
+            .chain(&self.tags)
+            .any(|text| text.to_lowercase().contains(&query))
+    }
+}
+
+pub type Notes = BTreeMap<KanjiId, KanjiNotes>;
+
+pub fn load() -> Result<Notes, String> {
+    Ok(storage::read_json(KANJI_NOTES_PATH)?.unwrap_or_default())
+}
+
+pub struct NotesState(RwLock<Notes>);
+
+impl NotesState {
+    pub fn new(notes: Notes) -> Self {
+        Self(RwLock::new(notes))
+    }
+
+    pub fn read(&self) -> Result<RwLockReadGuard<'_, Notes>, String> {
+        self.0.read().map_err(|_| "Failed to lock kanji notes".to_string())
+    }
+
+    pub fn write(&self) -> Result<RwLockWriteGuard<'_, Notes>, String> {
+        self.0.write().map_err(|_| "Failed to lock kanji notes".to_string())
+    }
+}
+
+#[tauri::command]
+pub fn get_kanji_notes(notes: State<'_, NotesState>, url: String) -> Result<KanjiNotes, String> {
+    let kanji_id = KanjiId::parse(&url).ok_or_else(|| format!("Invalid kanji link: {}", url))?;
+    Ok(notes.read()?.get(&kanji_id).cloned().unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn set_kanji_notes(
+    notes: State<'_, NotesState>,
+    history: State<'_, HistoryState>,
+    url: String,
+    kanji_notes: KanjiNotes
+) -> Result<(), String> {
+    let kanji_id = KanjiId::parse(&url).ok_or_else(|| format!("Invalid kanji link: {}", url))?;
+    let mut notes = notes.write()?;
+    let before = notes.get(&kanji_id).cloned();
+
+    if kanji_notes.is_empty() {
+        notes.remove(&kanji_id);
+    } else {
+        let mut tags: Vec<String> = kanji_notes.tags
+            .iter()
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        tags.sort();
+        tags.dedup();
+
+        notes.insert(kanji_id.clone(), KanjiNotes {
+            tags,
+            updated_at: storage::unix_now(),
+            ..kanji_notes
+        });
+    }
+
+    storage::write_json(KANJI_NOTES_PATH, &*notes)?;
+    history.record(format!("Edit notes for kanji #{}", kanji_id.index), Change::Notes {
+        after: notes.get(&kanji_id).cloned(),
+        kanji_id,
+        before,
+    })
+}
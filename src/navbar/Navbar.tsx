@@ -48,10 +48,7 @@ function Navbar() {
     const handleNavigation = (link: string | null | undefined) => {
         console.log(link)
         if (!link) return;
-        const absoluteUrl = link.startsWith('http') 
-            ? link 
-            : `https://www.kanjidamage.com${link}`;
-        navigate(`/kanji/${encodeURIComponent(absoluteUrl)}`);
+        navigate(`/kanji/${encodeURIComponent(link)}`);
     };
 
     const isImageUrl = (str: string) => {
@@ -112,9 +112,9 @@ function Home() {
                                                     onClick={() => handle_navigation(item.link, navigate)}
                                                     class="text-3xl font-bold text-gray-900 hover:text-blue-600"
                                                 >
-                                                    {item.kanji.startsWith('/') ? (
+                                                    {item.kanji.startsWith('http') ? (
                                                         <img 
-                                                            src={`https://www.kanjidamage.com/${item.kanji}`} 
+                                                            src={item.kanji} 
                                                             alt="Kanji character"
                                                             class="h-[1em] w-auto inline-block"
                                                             style="object-fit: contain"
@@ -68,52 +68,16 @@ function Kanji() {
         });
     }
 
-    function processHtmlContent(html: string) {
-        const tempDiv = document.createElement('div');
-        tempDiv.innerHTML = html;
-    
-        // Process any links or components if needed
-        tempDiv.querySelectorAll('a').forEach(a => {
-            const href = a.getAttribute('href');
-            if (href?.startsWith('/')) {
-                a.setAttribute('href', `https://www.kanjidamage.com${href}`);
-            }
-        });
-    
-        // Process any images if needed
-        tempDiv.querySelectorAll('img').forEach(img => {
-            const src = img.getAttribute('src');
-            if (src?.startsWith('/')) {
-                img.setAttribute('src', `https://www.kanjidamage.com${src}`);
-            }
-        });
-    
-        return tempDiv.innerHTML;
-    }
-    
-
     function processBreakdown(breakdown: string) {
         const tempDiv = document.createElement('div');
         tempDiv.innerHTML = breakdown;
         
-        // Process all image sources
+        // Image sources already point at the configured site, just size them
         tempDiv.querySelectorAll('img').forEach(img => {
-            const src = img.getAttribute('src');
-            if (src?.startsWith('/')) {
-                img.setAttribute('src', `https://www.kanjidamage.com${src}`);
-            }
             img.className = 'h-4 w-auto inline-block align-middle';
             img.style.objectFit = 'contain';
         });
         
-        // Process all component links
-        tempDiv.querySelectorAll('a.component').forEach(a => {
-            const href = a.getAttribute('href');
-            if (href?.startsWith('/')) {
-                a.setAttribute('href', `https://www.kanjidamage.com${href}`);
-            }
-        });
-        
         return tempDiv.innerHTML;
     }
 
@@ -228,9 +192,9 @@ function Kanji() {
                                     {/* Kanji character */}
                                     <div class="shrink-0">
                                         <span class="text-6xl md:text-8xl font-bold text-gray-800 font-japanese block">
-                                            {kanji()?.kanji.startsWith('/') ? (
+                                            {kanji()?.kanji.startsWith('http') ? (
                                                 <img 
-                                                    src={`https://www.kanjidamage.com/${kanji()?.kanji}`} 
+                                                    src={kanji()?.kanji} 
                                                     alt="Kanji character"
                                                     class="h-[1.5em] w-auto"
                                                     style="object-fit: contain"
@@ -362,7 +326,7 @@ function Kanji() {
                                     {/* <h2 class="text-xl font-semibold mb-4 text-gray-800"></h2> */}
                                     <div 
                                         class="text-gray-600 prose prose-sm"
-                                        innerHTML={processHtmlContent(kanji()?.description!)}
+                                        innerHTML={kanji()?.description!}
                                     />
                                 </div>
                             )}
@@ -372,7 +336,7 @@ function Kanji() {
                                     <h2 class="text-xl font-semibold mb-4 text-gray-800">Mnemonic</h2>
                                     <div 
                                         class="text-gray-600 prose prose-sm"
-                                        innerHTML={processHtmlContent(kanji()?.mnemonic!)}
+                                        innerHTML={kanji()?.mnemonic!}
                                         onClick={(e) => {
                                             const target = e.target as HTMLElement;
                                             if (target.classList.contains('component')) {
@@ -427,7 +391,7 @@ function Kanji() {
                                                                         {kun.reading}
                                                                         {kun.tags && kun.tags.map((tag: any) => (
                                                                             <span
-                                                                                // href={`https://www.kanjidamage.com/${tag.link}`}
+                                                                                // href={tag.link}
                                                                                 class="px-2 py-1 text-xs font-medium text-gray-600 bg-blue-100 rounded hover:bg-blue-200 transition-all duration-200"
                                                                             >
                                                                                 {tag.name}
@@ -464,7 +428,7 @@ function Kanji() {
                                                             <div class="flex gap-2">
                                                                 {jukugo.tags && jukugo.tags.map((tag: any) => (
                                                                     <span
-                                                                        // href={`https://www.kanjidamage.com/${tag.link}`}
+                                                                        // href={tag.link}
                                                                         class="px-2 py-1 text-xs font-medium text-gray-600 bg-blue-100 rounded hover:bg-blue-200 transition-all duration-200"
                                                                     >
                                                                         {tag.name}
@@ -66,9 +66,7 @@ function Kanjis() {
                 <div class="flex-grow">
                     {entry.has_image ? (
                         <img 
-                            src={entry.kanji.startsWith('http') 
-                                ? entry.kanji 
-                                : `https://www.kanjidamage.com${entry.kanji}`}
+                            src={entry.kanji}
                             alt={entry.meaning}
                             class="h-8 w-8 sm:h-10 sm:w-10 object-contain"
                             onError={(e) => {
@@ -1,29 +1,67 @@
-import { createSignal, createEffect, Show, For } from 'solid-js';
+import { createSignal, onMount, onCleanup, Show, For } from 'solid-js';
 import { invoke } from "@tauri-apps/api/core";
 import Navbar from '../../navbar/Navbar';
 import * as wanakana from 'wanakana';
 
-interface PracticeItem {
-    kanji: string;
-    onyomi: [string, string][];
-    kunyomi: Array<{ reading: string; description: string }>;
-    meanings: string[];
-    mnemonic: string | null;
-    breakdown: string;
-    lookalikes: Array<{ kanji: string; meaning: string }>;
-    jukugo: Array<{
-        japanese: string;
-        reading: string;
-        english: string;
-    }>;
+type PracticeType = 'Meaning' | 'Onyomi' | 'Kunyomi';
+
+// The practice types a session asks about each kanji
+const SESSION_TYPES: PracticeType[] = ['Meaning', 'Onyomi', 'Kunyomi'];
+
+interface PracticeCard {
+  kanji_id: string;
+  practice_type: PracticeType;
+  prompt: string;
+  answers: string[];
+  hint: string | null;
+}
+
+interface SessionItem {
+  kanji_id: string;
+  questions: PracticeCard[];
+  results: (boolean | null)[];
+  requeued: number;
+}
+
+interface SessionProgress {
+  total: number;
+  completed: number;
+  remaining: number;
+  answered: number;
+  correct: number;
+}
+
+interface AnswerResult {
+  correct: boolean;
+  answers: string[];
+  item_finished: boolean;
+  requeued: boolean;
+  progress: SessionProgress;
 }
 
-interface ValidationState {
-  meaning: boolean | null;
-  onyomi: boolean | null;
-  kunyomi: boolean | null;
+interface KanjiDetail {
+  mnemonic: string | null;
+  breakdown: string;
+  lookalikes: Array<{ kanji: string; meaning: string }>;
+  jukugo: Array<{
+    japanese: string;
+    reading: string;
+    english: string;
+  }>;
 }
 
+const LABELS: Record<PracticeType, string> = {
+  Meaning: 'Meaning',
+  Onyomi: "On'yomi",
+  Kunyomi: "Kun'yomi",
+};
+
+const PLACEHOLDERS: Record<PracticeType, string> = {
+  Meaning: 'Enter any meaning...',
+  Onyomi: "Enter the on'yomi reading...",
+  Kunyomi: "Enter the kun'yomi reading...",
+};
+
 const InfoSection = (props: { 
   title: string, 
   children: any, 
@@ -54,111 +92,113 @@ const InfoSection = (props: {
 };
 
 function Practice() {
-  const [practicePool, setPracticePool] = createSignal<PracticeItem[]>([]);
-  const [currentItem, setCurrentItem] = createSignal<PracticeItem | null>(null);
-  const [isCorrect, setIsCorrect] = createSignal<boolean | null>(null);
-  const [currentIndex, setCurrentIndex] = createSignal(0);
-  const [answers, setAnswers] = createSignal({
-    meaning: '',
-    onyomi: '',
-    kunyomi: ''
-  });
-  const [validationState, setValidationState] = createSignal<ValidationState>({
-    meaning: null,
-    onyomi: null,
-    kunyomi: null
-  });
+  const [progress, setProgress] = createSignal<SessionProgress | null>(null);
+  const [currentItem, setCurrentItem] = createSignal<SessionItem | null>(null);
+  const [detail, setDetail] = createSignal<KanjiDetail | null>(null);
+  const [answers, setAnswers] = createSignal<string[]>([]);
+  const [results, setResults] = createSignal<(AnswerResult | null)[]>([]);
   const [showSolutions, setShowSolutions] = createSignal(false);
   const [mnemonicOpen, setMnemonicOpen] = createSignal(false);
   const [componentsOpen, setComponentsOpen] = createSignal(false);
   const [similarKanjiOpen, setSimilarKanjiOpen] = createSignal(false);
   const [vocabularyOpen, setVocabularyOpen] = createSignal(false);
 
-  let kunyomiInputRef: HTMLInputElement | undefined;
-
-  createEffect(() => {
-    if (kunyomiInputRef) {
-      wanakana.bind(kunyomiInputRef, {
-        IMEMode: true,
-        customKanaMapping: { nn: 'ん' }
-      });
-      return () => {
-        if (kunyomiInputRef) {
-          wanakana.unbind(kunyomiInputRef);
-        }
-      };
+  const itemFinished = () => results().some(result => result?.item_finished);
+  const allCorrect = () => results().every(result => result?.correct);
+  const isImage = (prompt: string) => prompt.startsWith('http');
+
+  const bindKana = (input: HTMLInputElement) => {
+    wanakana.bind(input, {
+      IMEMode: true,
+      customKanaMapping: { nn: 'ん' }
+    });
+    onCleanup(() => wanakana.unbind(input));
+  };
+
+  // Pull the next item off the session queue, the session lives in the backend so it
+  // survives closing the window
+  const loadNext = async () => {
+    try {
+      const item = await invoke<SessionItem | null>('next_card');
+      setCurrentItem(item);
+      setDetail(null);
+      setAnswers(item ? item.questions.map(() => '') : []);
+      setResults(item ? item.questions.map(() => null) : []);
+      setShowSolutions(false);
+      setMnemonicOpen(false);
+      setComponentsOpen(false);
+      setSimilarKanjiOpen(false);
+      setVocabularyOpen(false);
+    } catch (error) {
+      console.error('Failed to load the next card:', error);
     }
-  });
+  };
 
-  createEffect(async () => {
+  const startSession = async () => {
     try {
-      const pool = await invoke<PracticeItem[]>('initialize_practice_pool');
-      setPracticePool(pool);
-      console.log(pool)
-      if (pool.length > 0) {
-        setCurrentItem(pool[0]);
+      setProgress(await invoke<SessionProgress>('start_session', { practiceTypes: SESSION_TYPES }));
+      await loadNext();
+    } catch (error) {
+      console.error('Failed to start a practice session:', error);
+    }
+  };
+
+  onMount(async () => {
+    try {
+      // Pick up where the last session left off
+      const unfinished = await invoke<SessionProgress | null>('get_session');
+      if (unfinished && unfinished.remaining > 0) {
+        setProgress(unfinished);
+        await loadNext();
+      } else {
+        await startSession();
       }
     } catch (error) {
-      console.error('Failed to initialize practice pool:', error);
+      console.error('Failed to load the practice session:', error);
     }
   });
 
-  const getInputClass = (field: keyof ValidationState) => {
+  const setAnswer = (question: number, value: string) => {
+    setAnswers(answers().map((answer, i) => i === question ? value : answer));
+  };
+
+  const getInputClass = (question: number) => {
     const baseClass = "w-full px-4 py-2 border rounded-md focus:outline-none focus:ring-2";
-    if (validationState()[field] === null) return `${baseClass} focus:ring-blue-500`;
-    return validationState()[field] 
+    const result = results()[question];
+    if (!result) return `${baseClass} focus:ring-blue-500`;
+    return result.correct
       ? `${baseClass} border-green-500 bg-green-50 focus:ring-green-500`
       : `${baseClass} border-red-500 bg-red-50 focus:ring-red-500`;
   };
 
-  const handleSubmit = (e: Event) => {
+  // Each question is graded on its own, one after the other since they share the session
+  const handleSubmit = async (e: Event) => {
     e.preventDefault();
-    const current = currentItem();
-    if (!current) return;
-  
-    const meaningCorrect = current.meanings ? 
-    current.meanings.some(meaning => 
-        answers().meaning.toLowerCase().trim() === meaning.toLowerCase().trim()
-    ) : true;
-    
-    const onyomiCorrect = current.onyomi && current.onyomi.length > 0 ? 
-      current.onyomi.some(([reading]) => reading.toLowerCase().trim() === answers().onyomi.toLowerCase().trim()) : true;
-    const kunyomiCorrect = current.kunyomi && current.kunyomi.length > 0 ? 
-      current.kunyomi.some(entry => entry.reading === answers().kunyomi) : true;
-  
-    setValidationState({
-      meaning: meaningCorrect,
-      onyomi: onyomiCorrect,
-      kunyomi: kunyomiCorrect
-    });
-  
-    setIsCorrect(meaningCorrect && onyomiCorrect && kunyomiCorrect);
-  };
+    const item = currentItem();
+    if (!item || itemFinished()) return;
 
-  const handleNext = () => {
-    const nextIndex = currentIndex() + 1;
-    if (nextIndex < practicePool().length) {
-      setCurrentIndex(nextIndex);
-      setCurrentItem(practicePool()[nextIndex]);
-      setAnswers({ meaning: '', onyomi: '', kunyomi: '' });
-      setIsCorrect(null);
-      setValidationState({ meaning: null, onyomi: null, kunyomi: null });
-      setShowSolutions(false);
-      setMnemonicOpen(false);
-      setComponentsOpen(false);
-      setSimilarKanjiOpen(false);
-      setVocabularyOpen(false);
+    const graded = [...results()];
+    for (let question = 0; question < item.questions.length; question++) {
+      if (graded[question]) continue;
+      try {
+        const result = await invoke<AnswerResult>('submit_answer', {
+          question,
+          answer: answers()[question].trim()
+        });
+        graded[question] = result;
+        setProgress(result.progress);
+      } catch (error) {
+        console.error('Failed to submit answer:', error);
+        break;
+      }
     }
-  };
+    setResults(graded);
 
-  const handleKunyomiInput = (e: InputEvent) => {
-    const input = (e.target as HTMLInputElement).value;
-    const hiragana = wanakana.toHiragana(input, {
-      IMEMode: true,
-      useObsoleteKana: false,
-      customKanaMapping: { nn: 'ん' }
-    });
-    setAnswers({...answers(), kunyomi: hiragana});
+    try {
+      setDetail(await invoke<KanjiDetail>('get_kanji', { url: item.kanji_id }));
+    } catch (error) {
+      console.error('Failed to load kanji details:', error);
+    }
   };
 
   return (
@@ -170,82 +210,61 @@ function Practice() {
             <Show
               when={currentItem()}
               fallback={
-                <div class="text-center text-gray-600">
-                  No kanji available for practice
+                <div class="text-center text-gray-600 space-y-4">
+                  <div>
+                    {progress()?.total ? 'Session complete!' : 'Nothing due for practice right now'}
+                  </div>
+                  <Show when={progress()?.total}>
+                    <div>{progress()!.correct} of {progress()!.answered} answers correct</div>
+                    <button
+                      type="button"
+                      onClick={startSession}
+                      class="bg-blue-500 text-white px-4 py-2 rounded-md hover:bg-blue-600 transition-colors"
+                    >
+                      Start New Session
+                    </button>
+                  </Show>
                 </div>
               }
             >
               <div class="text-center mb-8">
-                <div class="text-8xl mb-4 font-bold">{currentItem()!.kanji}</div>
+                <Show
+                  when={!isImage(currentItem()!.questions[0].prompt)}
+                  fallback={<img src={currentItem()!.questions[0].prompt} class="h-24 mx-auto mb-4" />}
+                >
+                  <div class="text-8xl mb-4 font-bold">{currentItem()!.questions[0].prompt}</div>
+                </Show>
               </div>
 
               <form onSubmit={handleSubmit} class="space-y-4">
-                <Show when={currentItem()?.meanings! && currentItem()?.meanings!.length! > 0}>
+                <For each={currentItem()!.questions}>
+                  {(card, question) => (
                     <div>
-                        <label class="block text-sm font-medium text-gray-700 mb-1">
-                        Meaning
-                        </label>
-                        <input
+                      <label class="block text-sm font-medium text-gray-700 mb-1">
+                        {LABELS[card.practice_type]}
+                      </label>
+                      <input
                         type="text"
-                        value={answers().meaning}
-                        onInput={(e) => setAnswers({...answers(), meaning: e.currentTarget.value})}
-                        class={getInputClass('meaning')}
-                        placeholder="Enter any meaning..."
-                        disabled={isCorrect()!}
-                        />
-                        <Show when={validationState().meaning === false}>
-                        <p class="mt-1 text-sm text-red-600">
-                            {/* Incorrect meaning. Try one of: {currentItem()?.meanings?.split('/').join(', ')} */}
-                        </p>
-                        </Show>
+                        value={answers()[question()]}
+                        onInput={(e) => setAnswer(question(), e.currentTarget.value)}
+                        class={getInputClass(question())}
+                        placeholder={PLACEHOLDERS[card.practice_type]}
+                        disabled={!!results()[question()]}
+                        ref={(el) => card.practice_type === 'Kunyomi' && bindKana(el)}
+                      />
+                      <Show when={results()[question()]?.correct === false}>
+                        <p class="mt-1 text-sm text-red-600">Incorrect {LABELS[card.practice_type].toLowerCase()}</p>
+                      </Show>
                     </div>
-                </Show>
-
-                <Show when={currentItem()?.onyomi && currentItem()?.onyomi.length! > 0}>
-                  <div>
-                    <label class="block text-sm font-medium text-gray-700 mb-1">
-                      On'yomi
-                    </label>
-                    <input
-                      type="text"
-                      value={answers().onyomi}
-                      onInput={(e) => setAnswers({...answers(), onyomi: e.currentTarget.value})}
-                      class={getInputClass('onyomi')}
-                      placeholder="Enter the on'yomi reading..."
-                      disabled={isCorrect()!}
-                    />
-                    <Show when={validationState().onyomi === false}>
-                      <p class="mt-1 text-sm text-red-600">Incorrect on'yomi</p>
-                    </Show>
-                  </div>
-                </Show>
-
-                <Show when={currentItem()?.kunyomi && currentItem()?.kunyomi.length! > 0}>
-                  <div>
-                    <label class="block text-sm font-medium text-gray-700 mb-1">
-                      Kun'yomi
-                    </label>
-                    <input
-                      type="text"
-                      value={answers().kunyomi}
-                      onInput={handleKunyomiInput}
-                      class={getInputClass('kunyomi')}
-                      placeholder="Enter the kun'yomi reading..."
-                      disabled={isCorrect()!}
-                      ref={kunyomiInputRef}
-                    />
-                    <Show when={validationState().kunyomi === false}>
-                      <p class="mt-1 text-sm text-red-600">Incorrect kun'yomi</p>
-                    </Show>
-                  </div>
-                </Show>
+                  )}
+                </For>
 
                 <Show
-                  when={!isCorrect()}
+                  when={!itemFinished()}
                   fallback={
                     <button
                       type="button"
-                      onClick={handleNext}
+                      onClick={loadNext}
                       class="w-full bg-green-500 text-white py-2 rounded-md hover:bg-green-600 transition-colors"
                     >
                       Next Kanji
@@ -260,7 +279,7 @@ function Practice() {
                   </button>
                 </Show>
 
-                <Show when={!isCorrect() && isCorrect() !== null}>
+                <Show when={itemFinished() && !allCorrect()}>
                   <button
                     type="button"
                     onClick={() => setShowSolutions(!showSolutions())}
@@ -271,46 +290,32 @@ function Practice() {
                 </Show>
               </form>
 
-              <Show when={showSolutions() && !isCorrect() && currentItem()}>
+              <Show when={showSolutions() && itemFinished() && !allCorrect()}>
                 <div class="mt-4 p-4 border rounded-md bg-gray-50">
                   <h3 class="font-medium mb-2">Solutions:</h3>
                   <div class="space-y-2">
-                    <Show when={currentItem()?.meanings}>
-                      <div>
-                        <span class="font-medium">Meaning: </span> 
-                            {currentItem()?.meanings.map((meaning: string) => 
-                            {
-                                return (
-                                    <span>
-                                        {meaning}, 
-                                    </span>
-                                )
-                            })}
-                      </div>
-                    </Show>
-                    <Show when={currentItem()?.onyomi}>
-                      <div>
-                        <span class="font-medium">On'yomi:</span> {currentItem()?.onyomi?.map(([reading]) => reading).join(', ')}
-                      </div>
-                    </Show>
-                    <Show when={currentItem()?.kunyomi}>
-                      <div>
-                        <span class="font-medium">Kun'yomi:</span> {currentItem()?.kunyomi?.[0]?.reading}
-                      </div>
-                    </Show>
+                    <For each={currentItem()!.questions}>
+                      {(card, question) => (
+                        <Show when={results()[question()]}>
+                          <div>
+                            <span class="font-medium">{LABELS[card.practice_type]}:</span> {results()[question()]!.answers.join(', ')}
+                          </div>
+                        </Show>
+                      )}
+                    </For>
                   </div>
                 </div>
               </Show>
 
-              <Show when={isCorrect() !== null}>
+              <Show when={itemFinished()}>
                 <div
                   class={`mt-4 p-3 rounded-md text-center ${
-                    isCorrect()
+                    allCorrect()
                       ? 'bg-green-100 text-green-700'
                       : 'bg-red-100 text-red-700'
                   }`}
                 >
-                  {isCorrect() ? 'All correct!' : 'Try again!'}
+                  {allCorrect() ? 'All correct!' : "This one will come back later"}
                 </div>
 
                 <div class="mt-6">
@@ -318,10 +323,10 @@ function Practice() {
                     title="Mnemonic" 
                     isOpen={mnemonicOpen()} 
                     onToggle={() => setMnemonicOpen(!mnemonicOpen())}
-                    available={!!currentItem()?.mnemonic}
+                    available={!!detail()?.mnemonic}
                   >
                     <div class="prose prose-sm max-w-none"
-                      innerHTML={currentItem()?.mnemonic || ""}
+                      innerHTML={detail()?.mnemonic || ""}
                     />
                   </InfoSection>
 
@@ -329,10 +334,10 @@ function Practice() {
                     title="Components" 
                     isOpen={componentsOpen()} 
                     onToggle={() => setComponentsOpen(!componentsOpen())}
-                    available={!!currentItem()?.breakdown && currentItem()?.breakdown.trim().length! > 0}
+                    available={!!detail()?.breakdown && detail()?.breakdown.trim().length! > 0}
                   >
                     <div class="prose prose-sm max-w-none" 
-                      innerHTML={currentItem()?.breakdown.trim()}
+                      innerHTML={detail()?.breakdown.trim()}
                     />
                   </InfoSection>
 
@@ -340,10 +345,10 @@ function Practice() {
                     title="Similar Kanji" 
                     isOpen={similarKanjiOpen()} 
                     onToggle={() => setSimilarKanjiOpen(!similarKanjiOpen())}
-                    available={currentItem()?.lookalikes! && currentItem()?.lookalikes!.length! > 0}
+                    available={detail()?.lookalikes! && detail()?.lookalikes!.length! > 0}
                   >
                     <div class="grid grid-cols-2 gap-4">
-                      <For each={currentItem()?.lookalikes}>
+                      <For each={detail()?.lookalikes}>
                         {(lookalike) => (
                           <div class="flex items-center space-x-2">
                           <span class="text-2xl">{lookalike.kanji}</span>
@@ -358,10 +363,10 @@ function Practice() {
                   title="Vocabulary" 
                   isOpen={vocabularyOpen()} 
                   onToggle={() => setVocabularyOpen(!vocabularyOpen())}
-                  available={currentItem()?.jukugo! && currentItem()?.jukugo!.length! > 0}
+                  available={detail()?.jukugo! && detail()?.jukugo!.length! > 0}
                 >
                   <div class="space-y-3">
-                    <For each={currentItem()?.jukugo}>
+                    <For each={detail()?.jukugo}>
                       {(word) => (
                         <div class="border-b pb-2">
                           <div class="font-medium">{word.japanese}</div>
@@ -376,7 +381,7 @@ function Practice() {
             </Show>
 
             <div class="mt-6 text-center text-gray-600">
-              Kanji {currentIndex() + 1} of {practicePool().length}
+              Kanji {(progress()?.completed ?? 0) + 1} of {progress()?.total ?? 0}
             </div>
           </Show>
         </div>
@@ -387,4 +392,3 @@ function Practice() {
 }
 
 export default Practice;
-
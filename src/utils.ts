@@ -36,8 +36,5 @@ export interface KanjiDetail {
 export function handle_navigation(link: string | null | undefined, navigate: any)
 {
     if (!link) return;
-    const absoluteUrl = link.startsWith('http') 
-        ? link 
-        : `https://www.kanjidamage.com${link}`;
-    navigate(`/kanji/${encodeURIComponent(absoluteUrl)}`);
+    navigate(`/kanji/${encodeURIComponent(link)}`);
 };